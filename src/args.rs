@@ -1,12 +1,37 @@
-use clap::{ArgGroup, Parser, Subcommand};
+use std::path::PathBuf;
+
+use clap::{ArgGroup, Parser, Subcommand, ValueEnum};
 
 #[derive(Parser, Debug)]
 #[command(author, version, about, long_about = None)]
 pub struct Args {
+    /// Output format
+    #[arg(short, long, global = true, value_enum, default_value_t)]
+    pub output: OutputFormat,
+
+    /// Config file to read plugins from (can be given multiple times). Defaults to the config
+    /// files loaded by tmux.
+    #[arg(long = "config", global = true, value_name = "PATH")]
+    pub configs: Vec<PathBuf>,
+
+    /// Directory to install plugins to (overrides `@tpm-plugins-dir` and
+    /// `TMUX_PLUGIN_MANAGER_PATH`)
+    #[arg(long, global = true, value_name = "PATH")]
+    pub plugins_dir: Option<PathBuf>,
+
     #[command(subcommand)]
     pub action: Action,
 }
 
+#[derive(ValueEnum, Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum OutputFormat {
+    /// Human readable output with progress bars
+    #[default]
+    Text,
+    /// Structured JSON on stdout
+    Json,
+}
+
 #[derive(Subcommand, Debug)]
 pub enum Action {
     /// Install plugins defined in tmux config
@@ -14,13 +39,30 @@ pub enum Action {
     /// Update installed plugins that are defined in tmux config
     Update(UpdateArgs),
     /// Load plugins defined in tmux config
-    Load,
+    Load(LoadArgs),
     /// Clean plugins not listed in tmux config
-    Clean,
+    Clean(CleanArgs),
     /// Run `install`, `clean` and `update --all` in one go
-    Sync,
+    Sync(SyncArgs),
+    /// Revert the key bindings, options and hooks plugins set in tmux when they were loaded
+    Unload(UnloadArgs),
+    /// Stop loading plugins without uninstalling them
+    Disable(ToggleArgs),
+    /// Load plugins again that have been disabled with `disable`
+    Enable(ToggleArgs),
+    /// Roll back plugins to the revision they were at before their last update
+    Rollback(RollbackArgs),
+    /// Show available updates of installed plugins (same as `update --all --dry-run`)
+    Outdated,
     /// Initialize Ahiru-TPM and load plugins
     Init,
+    /// List configured plugins and orphaned plugin directories
+    List,
+    /// Show the log of operations on plugins
+    Log(LogArgs),
+    /// Check the config and environment for problems without changing anything
+    #[command(visible_alias = "doctor")]
+    Check(CheckArgs),
 }
 
 #[derive(Parser, Debug)]
@@ -30,6 +72,35 @@ pub struct InstallArgs {
     pub load: bool,
 }
 
+#[derive(Parser, Debug)]
+pub struct LogArgs {
+    /// Only show operations on these plugins
+    pub names: Vec<String>,
+
+    /// Only show failed operations
+    #[arg(short, long)]
+    pub failed: bool,
+
+    /// Number of operations to show
+    #[arg(short = 'n', long, default_value_t = 20)]
+    pub limit: usize,
+
+    /// Show the most recent failed operation in full, including its output
+    #[arg(long, conflicts_with_all = ["failed", "limit"])]
+    pub last_failure: bool,
+
+    /// Show the full log of the last time plugins have been loaded
+    #[arg(long, conflicts_with_all = ["names", "failed", "limit", "last_failure"])]
+    pub last_run: bool,
+}
+
+#[derive(Parser, Debug)]
+pub struct LoadArgs {
+    /// Print how long each plugin and each of its init files took to load
+    #[arg(short, long)]
+    pub profile: bool,
+}
+
 #[derive(Parser, Debug)]
 #[command(group(
     ArgGroup::new("target")
@@ -45,6 +116,67 @@ pub struct UpdateArgs {
     #[arg(short, long)]
     pub load: bool,
 
+    /// Only show available updates without applying them
+    #[arg(short = 'n', long, conflicts_with = "load")]
+    pub dry_run: bool,
+
     /// List of plugins to update
     pub names: Vec<String>,
 }
+
+#[derive(Parser, Debug)]
+pub struct CleanArgs {
+    /// Only show which plugin directories would be removed
+    #[arg(short = 'n', long)]
+    pub dry_run: bool,
+
+    /// Don't ask for confirmation
+    #[arg(short, long)]
+    pub yes: bool,
+
+    /// Also remove plugins with uncommitted changes or unpushed commits
+    #[arg(short, long)]
+    pub force: bool,
+
+    /// Revert the changes removed plugins made to the running tmux server
+    #[arg(short, long)]
+    pub unload: bool,
+}
+
+#[derive(Parser, Debug)]
+pub struct CheckArgs {
+    /// Exit with a non-zero status on warnings as well, e.g. for plugins that are not installed
+    #[arg(long)]
+    pub strict: bool,
+}
+
+#[derive(Parser, Debug)]
+pub struct ToggleArgs {
+    /// List of plugins
+    #[arg(required = true)]
+    pub names: Vec<String>,
+}
+
+#[derive(Parser, Debug)]
+pub struct UnloadArgs {
+    /// List of plugins to unload
+    #[arg(required = true)]
+    pub names: Vec<String>,
+}
+
+#[derive(Parser, Debug)]
+pub struct SyncArgs {
+    /// Don't ask for confirmation before removing plugins
+    #[arg(short, long)]
+    pub yes: bool,
+}
+
+#[derive(Parser, Debug)]
+pub struct RollbackArgs {
+    /// Load plugins after rollback
+    #[arg(short, long)]
+    pub load: bool,
+
+    /// List of plugins to roll back (defaults to all plugins changed by an update)
+    pub names: Vec<String>,
+}
@@ -1,10 +1,52 @@
-use strum::{Display, EnumString};
+use strum::{Display, EnumIter, EnumString};
 
-#[derive(EnumString, Display, Debug, Eq, Hash, PartialEq, Clone)]
+#[derive(EnumString, EnumIter, Display, Debug, Eq, Hash, PartialEq, Clone)]
 pub enum Attribute {
     #[strum(serialize = "alias")]
     Alias,
 
     #[strum(serialize = "parallel")]
     Parallel,
+
+    #[strum(serialize = "tag")]
+    Tag,
+
+    #[strum(serialize = "rev")]
+    Rev,
+
+    #[strum(serialize = "build")]
+    Build,
+
+    #[strum(serialize = "post_install")]
+    PostInstall,
+
+    #[strum(serialize = "post_update")]
+    PostUpdate,
+
+    #[strum(serialize = "os")]
+    Os,
+
+    #[strum(serialize = "host")]
+    Host,
+
+    #[strum(serialize = "env")]
+    Env,
+
+    #[strum(serialize = "if")]
+    If,
+
+    #[strum(serialize = "after")]
+    After,
+
+    #[strum(serialize = "before")]
+    Before,
+
+    #[strum(serialize = "depends")]
+    Depends,
+
+    #[strum(serialize = "timeout")]
+    Timeout,
+
+    #[strum(serialize = "enabled")]
+    Enabled,
 }
@@ -1,59 +1,221 @@
-use std::process::exit;
+use std::{
+    process::exit,
+    time::{Duration, Instant},
+};
 
-use anyhow::Result;
+use anyhow::{Result, bail};
+use serde_json::json;
 
 use crate::{
-    args::{InstallArgs, UpdateArgs},
-    plugins::{clean, init, install, load, sync, update},
+    args::{
+        CheckArgs, CleanArgs, InstallArgs, LoadArgs, LogArgs, RollbackArgs, SyncArgs, ToggleArgs,
+        UnloadArgs, UpdateArgs,
+    },
+    output,
+    plugins::{
+        check::{self, Status},
+        clean::{self, CleanOptions},
+        disable, history, init, install, list,
+        load::{self, LoadResult},
+        rollback, sync, unload, update,
+    },
+    report,
     tmux::is_tmux_running,
+    utils,
 };
 
 pub async fn install(args: InstallArgs) -> Result<()> {
-    install::install().await?;
+    let results = install::install().await?;
 
     if args.load {
-        load()?;
+        reload()?;
+    }
+
+    if output::is_json() {
+        output::print_json(&json!({ "command": "install", "results": results }))?;
     }
 
     Ok(())
 }
 
 pub async fn update(args: UpdateArgs) -> Result<()> {
-    if args.all {
-        update::update_all().await?;
+    let results = if args.all {
+        update::update_all(args.dry_run).await?
     } else {
-        update::update_list(&args.names).await?;
+        update::update_list(&args.names, args.dry_run).await?
+    };
+
+    if args.load {
+        reload()?;
     }
 
+    if output::is_json() {
+        output::print_json(&json!({
+            "command": "update",
+            "dry_run": args.dry_run,
+            "results": results,
+        }))?;
+    }
+
+    Ok(())
+}
+
+pub async fn outdated() -> Result<()> {
+    update(UpdateArgs {
+        all: true,
+        load: false,
+        dry_run: true,
+        names: vec![],
+    })
+    .await
+}
+
+pub fn rollback(args: RollbackArgs) -> Result<()> {
+    let results = rollback::rollback(&args.names)?;
+
     if args.load {
-        load()?;
+        reload()?;
+    }
+
+    if output::is_json() {
+        output::print_json(&json!({ "command": "rollback", "results": results }))?;
     }
 
     Ok(())
 }
 
-pub fn load() -> Result<()> {
+pub fn load(args: LoadArgs) -> Result<()> {
+    let Some((results, total)) = reload_with_results()? else {
+        if output::is_json() {
+            output::print_json(&json!({ "command": "load", "reloaded": false }))?;
+        }
+        return Ok(());
+    };
+    let ok = results.iter().all(|result| !result.is_failed());
+
+    if output::is_json() {
+        output::print_json(&json!({
+            "command": "load",
+            "reloaded": true,
+            "ok": ok,
+            "total_ms": utils::as_millis(total),
+            "results": results,
+        }))?;
+    } else if args.profile {
+        load::print_profile(&results, total);
+    }
+
+    let failures = load::report("load", &results);
+    // The failures are part of the JSON output already
+    if failures.is_err() && output::is_json() {
+        exit(1);
+    }
+    failures
+}
+
+/// Reload plugins if tmux is running. Returns whether plugins have been reloaded.
+fn reload() -> Result<bool> {
+    match reload_with_results()? {
+        Some((results, _)) => load::report("load", &results).map(|()| true),
+        None => Ok(false),
+    }
+}
+
+/// Reload plugins if tmux is running. Returns the results of all plugins and the total load time
+/// if plugins have been reloaded.
+fn reload_with_results() -> Result<Option<(Vec<LoadResult>, Duration)>> {
     if !is_tmux_running() {
         eprintln!("WARN: Tmux is not running, plugins are not reloaded.");
-        return Ok(());
+        return Ok(None);
+    }
+    let start = Instant::now();
+    let results = load::load()?;
+    let total = start.elapsed();
+
+    if results.iter().all(|result| !result.is_failed()) {
+        eprintln!("==> Plugins have been reloaded.");
+    }
+    Ok(Some((results, total)))
+}
+
+pub fn clean(args: CleanArgs) -> Result<()> {
+    let result = clean::clean(CleanOptions {
+        dry_run: args.dry_run,
+        yes: args.yes,
+        force: args.force,
+        unload: args.unload,
+    })?;
+
+    if output::is_json() {
+        output::print_json(&json!({
+            "command": "clean",
+            "dry_run": args.dry_run,
+            "removed": result.removed,
+            "kept": result.kept,
+            "unloaded": result.unloaded,
+        }))?;
+    } else {
+        eprintln!("==> Done");
+    }
+
+    Ok(())
+}
+
+pub fn unload(args: UnloadArgs) -> Result<()> {
+    let results = unload::unload(&args.names)?;
+    let failed = results.iter().filter(|result| result.is_failed()).count();
+
+    if output::is_json() {
+        output::print_json(&json!({ "command": "unload", "ok": failed == 0, "results": results }))?;
+        if failed > 0 {
+            exit(1);
+        }
+    } else {
+        unload::print_results(&results);
     }
-    load::load()?;
 
-    eprintln!("==> Plugins have been reloaded.");
+    if failed > 0 {
+        bail!("Failed to unload {failed} plugin(s) completely");
+    }
     Ok(())
 }
 
-pub fn clean() -> Result<()> {
-    clean::clean()?;
+pub async fn sync(args: SyncArgs) -> Result<()> {
+    let results = sync::sync(CleanOptions {
+        yes: args.yes,
+        ..CleanOptions::default()
+    })
+    .await?;
+    reload()?;
+
+    if output::is_json() {
+        output::print_json(&json!({
+            "command": "sync",
+            "install": results.install,
+            "clean": results.clean,
+            "update": results.update,
+        }))?;
+    }
+
+    Ok(())
+}
 
-    eprintln!("==> Done");
+pub fn disable(args: ToggleArgs) -> Result<()> {
+    disable::disable(&args.names)?;
 
+    if output::is_json() {
+        output::print_json(&json!({ "command": "disable", "plugins": args.names }))?;
+    }
     Ok(())
 }
 
-pub async fn sync() -> Result<()> {
-    sync::sync().await?;
-    load()
+pub fn enable(args: ToggleArgs) -> Result<()> {
+    disable::enable(&args.names)?;
+
+    if output::is_json() {
+        output::print_json(&json!({ "command": "enable", "plugins": args.names }))?;
+    }
+    Ok(())
 }
 
 pub fn init() -> Result<()> {
@@ -64,3 +226,83 @@ pub fn init() -> Result<()> {
 
     init::init()
 }
+
+pub fn list() -> Result<()> {
+    let list = list::list()?;
+
+    if output::is_json() {
+        output::print_json(&json!({
+            "command": "list",
+            "plugins": list.plugins,
+            "orphans": list.orphans,
+        }))
+    } else {
+        list::print_list(&list);
+        Ok(())
+    }
+}
+
+pub fn check(args: CheckArgs) -> Result<()> {
+    let checks = check::check();
+    let ok = checks.iter().all(|check| match check.status {
+        Status::Ok => true,
+        Status::Warning => !args.strict,
+        Status::Error => false,
+    });
+
+    if output::is_json() {
+        output::print_json(&json!({ "command": "check", "ok": ok, "checks": checks }))?;
+    } else {
+        check::print_checks(&checks);
+    }
+
+    if !ok {
+        exit(1);
+    }
+
+    Ok(())
+}
+
+pub fn log(args: LogArgs) -> Result<()> {
+    if args.last_run {
+        return last_run_log();
+    }
+
+    if args.last_failure {
+        let entry = history::last_failure(&args.names)?;
+        if output::is_json() {
+            return output::print_json(&json!({ "command": "log", "last_failure": entry }));
+        }
+        match entry {
+            Some(entry) => history::print_entry(&entry),
+            None => eprintln!("==> No failures have been logged"),
+        }
+        return Ok(());
+    }
+
+    let entries = history::history(&args.names, args.failed, args.limit)?;
+    if output::is_json() {
+        output::print_json(&json!({ "command": "log", "entries": entries }))
+    } else {
+        history::print_history(&entries);
+        Ok(())
+    }
+}
+
+fn last_run_log() -> Result<()> {
+    let log = report::load_last_run()?;
+
+    if output::is_json() {
+        output::print_json(&json!({
+            "command": "log",
+            "path": report::get_last_run_log_path(),
+            "log": log,
+        }))
+    } else {
+        match log {
+            Some(log) => print!("{log}"),
+            None => eprintln!("==> Plugins have not been loaded yet"),
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,99 @@
+use std::{collections::HashMap, env};
+
+use cached::proc_macro::cached;
+use cmd_lib::{run_cmd, run_fun};
+
+use crate::attribute::Attribute;
+
+/// Evaluate the activation conditions (`os`, `host`, `env` and `if`) of a plugin. Returns the
+/// reason why the plugin is skipped if any of them does not hold.
+pub fn evaluate(attributes: &HashMap<Attribute, String>) -> Option<String> {
+    let conditions = [
+        Attribute::Os,
+        Attribute::Host,
+        Attribute::Env,
+        Attribute::If,
+    ];
+
+    conditions.into_iter().find_map(|attribute| {
+        let value = attributes.get(&attribute)?;
+        let holds = match attribute {
+            Attribute::Os => value == env::consts::OS,
+            Attribute::Host => matches_hostname(value),
+            Attribute::Env => env::var_os(value).is_some_and(|val| !val.is_empty()),
+            Attribute::If => run_condition(value.to_owned()),
+            _ => unreachable!("Not a condition: {attribute}"),
+        };
+
+        (!holds).then(|| match attribute {
+            Attribute::Os => format!("os={value} does not match {}", env::consts::OS),
+            Attribute::Host => format!(
+                "host={value} does not match {}",
+                hostname().unwrap_or_else(|| "unknown host".into())
+            ),
+            Attribute::Env => format!("env={value} is not set"),
+            _ => format!("if={value} failed"),
+        })
+    })
+}
+
+/// Whether `host` is the full or short hostname of this machine
+fn matches_hostname(host: &str) -> bool {
+    hostname().is_some_and(|hostname| {
+        hostname == host
+            || hostname
+                .split('.')
+                .next()
+                .is_some_and(|short| short == host)
+    })
+}
+
+#[cached]
+fn hostname() -> Option<String> {
+    // Quoted, so that rustfmt doesn't turn the flag into `- n`
+    run_fun!(uname "-n").ok()
+}
+
+/// Run a shell command and check whether it succeeds. Results are cached, so that every command
+/// only runs once.
+#[cached]
+fn run_condition(command: String) -> bool {
+    run_cmd!(sh -c $command >/dev/null 2>&1).is_ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_evaluate() {
+        let evaluate_one =
+            |attribute, value: &str| evaluate(&HashMap::from([(attribute, value.to_owned())]));
+
+        assert_eq!(evaluate(&HashMap::new()), None);
+        assert_eq!(evaluate_one(Attribute::Os, env::consts::OS), None);
+        assert_eq!(
+            evaluate_one(Attribute::Os, "plan9"),
+            Some(format!("os=plan9 does not match {}", env::consts::OS))
+        );
+        assert_eq!(evaluate_one(Attribute::Env, "PATH"), None);
+        assert_eq!(
+            evaluate_one(Attribute::Env, "AHIRU_TPM_TEST_UNSET"),
+            Some("env=AHIRU_TPM_TEST_UNSET is not set".into())
+        );
+        assert_eq!(evaluate_one(Attribute::If, "true"), None);
+        assert_eq!(
+            evaluate_one(Attribute::If, "exit 1"),
+            Some("if=exit 1 failed".into())
+        );
+
+        let hostname = hostname().expect("Hostname should be known");
+        assert_eq!(evaluate_one(Attribute::Host, &hostname), None);
+        assert_eq!(
+            evaluate_one(Attribute::Host, "ahiru-tpm-test-host"),
+            Some(format!(
+                "host=ahiru-tpm-test-host does not match {hostname}"
+            ))
+        );
+    }
+}
@@ -0,0 +1,184 @@
+use std::{
+    error::Error,
+    fmt::{self, Display, Formatter},
+    fs,
+    path::{Path, PathBuf},
+};
+
+use colored::Colorize;
+use pest::{RuleType, iterators::Pair};
+use serde::Serialize;
+
+/// A position inside of a config file. Lines and columns start at 1.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Location {
+    pub path: PathBuf,
+    pub line: usize,
+    pub col: usize,
+}
+
+impl Location {
+    pub fn new(path: &Path, line: usize, col: usize) -> Location {
+        Location {
+            path: path.to_owned(),
+            line,
+            col,
+        }
+    }
+
+    pub fn from_pair<R: RuleType>(path: &Path, pair: &Pair<'_, R>) -> Location {
+        let (line, col) = pair.line_col();
+        Location::new(path, line, col)
+    }
+
+    /// The location `offset` characters to the right of this one
+    pub fn offset(&self, offset: usize) -> Location {
+        Location {
+            col: self.col + offset,
+            ..self.clone()
+        }
+    }
+}
+
+impl Display for Location {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}:{}", self.path.display(), self.line, self.col)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+/// A problem in a config file, rendered like a compiler message:
+///
+/// ```text
+/// error: Invalid plugin spec: expected EOI
+///  --> ~/.config/tmux/tmux.conf:3:20
+///   |
+/// 3 | set -g @plugin 'foo bar'
+///   |                    ^
+///   = hint: Check the spec
+/// ```
+#[derive(Debug, Clone)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub message: String,
+    pub location: Location,
+    pub hint: Option<String>,
+    source_line: Option<String>,
+}
+
+impl Diagnostic {
+    pub fn error(location: Location, message: impl Into<String>) -> Diagnostic {
+        Diagnostic::new(Severity::Error, location, message.into())
+    }
+
+    pub fn warning(location: Location, message: impl Into<String>) -> Diagnostic {
+        Diagnostic::new(Severity::Warning, location, message.into())
+    }
+
+    fn new(severity: Severity, location: Location, message: String) -> Diagnostic {
+        let source_line = fs::read_to_string(&location.path).ok().and_then(|content| {
+            content
+                .lines()
+                .nth(location.line.saturating_sub(1))
+                .map(str::to_owned)
+        });
+
+        Diagnostic {
+            severity,
+            message,
+            location,
+            hint: None,
+            source_line,
+        }
+    }
+
+    pub fn with_hint(mut self, hint: impl Into<String>) -> Diagnostic {
+        self.hint = Some(hint.into());
+        self
+    }
+
+    /// Print the diagnostic to stderr
+    pub fn report(&self) {
+        eprintln!("{self}");
+    }
+}
+
+impl Display for Diagnostic {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let severity = match self.severity {
+            Severity::Error => "error".bold().red(),
+            Severity::Warning => "warning".bold().yellow(),
+        };
+        let gutter = " ".repeat(self.location.line.to_string().len());
+        let bar = "|".bold().blue();
+
+        writeln!(f, "{severity}: {}", self.message.bold())?;
+        write!(f, "{gutter}{} {}", "-->".bold().blue(), self.location)?;
+
+        if let Some(line) = &self.source_line {
+            // Keep tabs so that the caret lines up with the offending character
+            let indent: String = line
+                .chars()
+                .take(self.location.col.saturating_sub(1))
+                .map(|c| if c == '\t' { '\t' } else { ' ' })
+                .collect();
+
+            writeln!(f)?;
+            writeln!(f, "{gutter} {bar}")?;
+            writeln!(
+                f,
+                "{} {bar} {line}",
+                self.location.line.to_string().bold().blue()
+            )?;
+            write!(f, "{gutter} {bar} {indent}{}", "^".bold().red())?;
+        }
+
+        if let Some(hint) = &self.hint {
+            writeln!(f)?;
+            write!(
+                f,
+                "{gutter} {} {}: {hint}",
+                "=".bold().blue(),
+                "hint".bold()
+            )?;
+        }
+
+        Ok(())
+    }
+}
+
+impl Error for Diagnostic {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render() {
+        colored::control::set_override(false);
+
+        let path = std::env::temp_dir().join("ahiru-tpm-test-diagnostic.conf");
+        fs::write(&path, "# plugins\n\tset -g @plugin 'foo bar'\n").unwrap();
+
+        let diagnostic = Diagnostic::error(Location::new(&path, 2, 17), "Invalid plugin spec")
+            .with_hint("Check the spec");
+
+        assert_eq!(
+            diagnostic.to_string(),
+            format!(
+                "error: Invalid plugin spec\n \
+                 --> {}:2:17\n  \
+                 |\n\
+                 2 | \tset -g @plugin 'foo bar'\n  \
+                 | \t               ^\n  \
+                 = hint: Check the spec",
+                path.display()
+            )
+        );
+    }
+}
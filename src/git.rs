@@ -0,0 +1,74 @@
+use std::path::Path;
+
+use anyhow::{Context, Result};
+use cmd_lib::run_fun;
+use serde::Serialize;
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct Commit {
+    pub sha: String,
+    pub subject: String,
+}
+
+impl Commit {
+    pub fn short_sha(&self) -> &str {
+        &self.sha[..self.sha.len().min(7)]
+    }
+}
+
+/// Get the SHA of the commit `HEAD` points to in the repository at `path`
+pub fn head_commit(path: &Path) -> Result<String> {
+    run_fun!(git -C $path rev-parse HEAD)
+        .with_context(|| format!("Failed to get HEAD commit of: {}", path.display()))
+}
+
+/// Get the name of the checked out branch or `None` if `HEAD` is detached
+pub fn current_branch(path: &Path) -> Option<String> {
+    run_fun!(git -C $path symbolic-ref --quiet --short HEAD 2>/dev/null).ok()
+}
+
+/// Resolve a revision (e.g. a possibly abbreviated SHA) to the full SHA of a commit
+pub fn resolve_commit(path: &Path, rev: &str) -> Option<String> {
+    let rev = format!("{rev}^{{commit}}");
+    run_fun!(git -C $path rev-parse --verify --quiet $rev).ok()
+}
+
+/// List the commits in `range` (e.g. `HEAD..@{upstream}`), newest first
+pub fn log(path: &Path, range: &str) -> Result<Vec<Commit>> {
+    let log = run_fun!(git -C $path log --format=%H%x09%s $range)
+        .with_context(|| format!("Failed to get log of: {}", path.display()))?;
+
+    Ok(log
+        .lines()
+        .filter_map(|line| line.split_once('\t'))
+        .map(|(sha, subject)| Commit {
+            sha: sha.to_owned(),
+            subject: subject.to_owned(),
+        })
+        .collect())
+}
+
+/// Describe the work in the repository at `path` that would be lost by deleting it, i.e.
+/// uncommitted changes and commits on local branches that are not on any remote branch or tag.
+/// Returns `None` if there is no such work.
+pub fn local_work(path: &Path) -> Result<Option<String>> {
+    // Don't look at a repository the directory might be part of
+    if !path.join(".git").exists() {
+        return Ok(None);
+    }
+
+    let status = run_fun!(git -C $path status --porcelain 2>/dev/null)
+        .with_context(|| format!("Failed to get status of: {}", path.display()))?;
+    if !status.is_empty() {
+        return Ok(Some("has uncommitted changes".into()));
+    }
+
+    let unpushed =
+        run_fun!(git -C $path rev-list --count --branches --not --remotes --tags 2>/dev/null)
+            .with_context(|| format!("Failed to get unpushed commits of: {}", path.display()))?;
+    Ok(match unpushed.trim() {
+        "0" => None,
+        "1" => Some("has 1 unpushed commit".into()),
+        count => Some(format!("has {count} unpushed commits")),
+    })
+}
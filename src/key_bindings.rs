@@ -19,6 +19,22 @@ impl KeyBindings {
             sync_key: get_option("@tpm-bind-sync"),
         }
     }
+
+    /// The keys of the Ahiru-TPM actions by action name, falling back to the default keys
+    fn keys(&self) -> [(&'static str, String); 4] {
+        let key = |key: &Option<String>, default: &str| key.clone().unwrap_or(default.into());
+        [
+            ("install", key(&self.install_key, "M-I")),
+            ("update", key(&self.update_key, "M-U")),
+            ("clean", key(&self.clean_key, "M-C")),
+            ("sync", key(&self.sync_key, "M-S")),
+        ]
+    }
+}
+
+/// The keys Ahiru-TPM binds its actions to, by action name
+pub fn keys() -> [(&'static str, String); 4] {
+    KeyBindings::from_tmux().keys()
 }
 
 pub fn setup() -> Result<()> {
@@ -30,10 +46,7 @@ pub fn setup() -> Result<()> {
 
     // --- Ahiru-TPM sensible keymaps ---
 
-    let install_key = binds.install_key.unwrap_or("M-I".into());
-    let update_key = binds.update_key.unwrap_or("M-U".into());
-    let clean_key = binds.clean_key.unwrap_or("M-C".into());
-    let sync_key = binds.sync_key.unwrap_or("M-S".into());
+    let [install_key, update_key, clean_key, sync_key] = binds.keys().map(|(_, key)| key);
 
     run_cmd!(
         tmux bind-key $install_key display-popup r"ahiru-tpm install --load; echo $'\n--- Done. Press ESC to close this popup. ---'";
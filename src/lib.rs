@@ -1,14 +1,25 @@
 pub mod args;
 pub mod attribute;
 pub mod cmd;
+pub mod condition;
+pub mod diagnostic;
+pub mod git;
 pub mod key_bindings;
+pub mod lockfile;
+pub mod operation_log;
+pub mod output;
 pub mod plugin;
 pub mod plugins;
 pub mod prefix_lines;
 pub mod progress_status;
 pub mod repo_url;
+pub mod report;
 pub mod spec;
+pub mod state;
+#[cfg(test)]
+mod test_utils;
 pub mod tmux;
+pub mod tmux_changes;
 pub mod tmux_config_parser;
 pub mod truncate_ellipsis;
 pub mod utils;
@@ -0,0 +1,192 @@
+use std::{collections::BTreeMap, fs, path::PathBuf};
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::{git, plugin::Plugin, tmux};
+
+/// The lockfile (`ahiru-tpm.lock`) records the exact commit of every installed plugin so that
+/// installs are reproducible across machines.
+#[derive(Serialize, Deserialize, Debug, Default, PartialEq)]
+pub struct Lockfile {
+    #[serde(skip)]
+    path: PathBuf,
+
+    plugins: BTreeMap<String, LockedPlugin>,
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
+pub struct LockedPlugin {
+    pub url: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub branch: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tag: Option<String>,
+    pub commit: String,
+}
+
+impl Lockfile {
+    /// Load the lockfile from its default location. A missing lockfile results in an empty one.
+    pub fn load() -> Result<Lockfile> {
+        Self::load_from(tmux::get_lockfile_path())
+    }
+
+    pub fn load_from(path: PathBuf) -> Result<Lockfile> {
+        if !path.exists() {
+            return Ok(Lockfile {
+                path,
+                ..Default::default()
+            });
+        }
+
+        let content = fs::read_to_string(&path)
+            .with_context(|| format!("Failed reading lockfile: {}", path.display()))?;
+        let mut lockfile: Lockfile = serde_json::from_str(&content)
+            .with_context(|| format!("Failed to parse lockfile: {}", path.display()))?;
+        lockfile.path = path;
+
+        Ok(lockfile)
+    }
+
+    pub fn save(&self) -> Result<()> {
+        if let Some(dir) = self.path.parent() {
+            fs::create_dir_all(dir)
+                .with_context(|| format!("Failed to create directory: {}", dir.display()))?;
+        }
+
+        let content = serde_json::to_string_pretty(self).context("Failed to serialize lockfile")?;
+        fs::write(&self.path, content + "\n")
+            .with_context(|| format!("Failed writing lockfile: {}", self.path.display()))
+    }
+
+    pub fn get(&self, name: &str) -> Option<&LockedPlugin> {
+        self.plugins.get(name)
+    }
+
+    /// Get the locked commit for a plugin. Entries whose URL, branch or tag differ from the
+    /// plugin are considered stale and are ignored. Plugins pinned to a `rev` and local plugins
+    /// are never locked.
+    pub fn locked_commit(&self, plugin: &Plugin) -> Option<&str> {
+        if plugin.rev().is_some() || plugin.is_local() {
+            return None;
+        }
+
+        self.get(plugin.name())
+            .filter(|locked| locked.url == plugin.url().as_str())
+            .filter(|locked| locked.tag.as_deref() == plugin.tag())
+            .filter(|locked| {
+                plugin
+                    .branch()
+                    .is_none_or(|branch| locked.branch.as_deref() == Some(branch))
+            })
+            .map(|locked| locked.commit.as_str())
+    }
+
+    /// Record the currently checked out commit of an installed plugin. Local plugins are not
+    /// managed by Ahiru-TPM and are therefore not locked.
+    pub fn lock(&mut self, plugin: &Plugin) -> Result<()> {
+        if plugin.is_local() {
+            return Ok(());
+        }
+
+        let path = plugin.path();
+        let locked = LockedPlugin {
+            url: plugin.url().to_string(),
+            branch: git::current_branch(path).or_else(|| plugin.branch().map(str::to_owned)),
+            tag: plugin.tag().map(str::to_owned),
+            commit: git::head_commit(path)?,
+        };
+        self.plugins.insert(plugin.name().to_owned(), locked);
+        Ok(())
+    }
+
+    /// Drop the entry of a plugin that has been removed. Returns whether there was one.
+    pub fn remove(&mut self, name: &str) -> bool {
+        self.plugins.remove(name).is_some()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::spec::Spec;
+
+    fn lockfile_with(name: &str, locked: LockedPlugin) -> Lockfile {
+        Lockfile {
+            path: PathBuf::new(),
+            plugins: BTreeMap::from([(name.to_owned(), locked)]),
+        }
+    }
+
+    #[test]
+    fn test_locked_commit() {
+        let plugin = Plugin::from(Spec::try_from("user/repo").unwrap());
+        let lockfile = lockfile_with(
+            "repo",
+            LockedPlugin {
+                url: "https://github.com/user/repo.git".into(),
+                branch: Some("main".into()),
+                tag: None,
+                commit: "0123abcd".into(),
+            },
+        );
+        assert_eq!(lockfile.locked_commit(&plugin), Some("0123abcd"));
+    }
+
+    #[test]
+    fn test_locked_commit_ignores_stale_entries() {
+        let lockfile = lockfile_with(
+            "repo",
+            LockedPlugin {
+                url: "https://github.com/user/repo.git".into(),
+                branch: Some("main".into()),
+                tag: None,
+                commit: "0123abcd".into(),
+            },
+        );
+
+        let other_url = Plugin::from(Spec::try_from("other/repo").unwrap());
+        assert_eq!(lockfile.locked_commit(&other_url), None);
+
+        let other_branch = Plugin::from(Spec::try_from("user/repo#develop").unwrap());
+        assert_eq!(lockfile.locked_commit(&other_branch), None);
+
+        let tagged = Plugin::from(Spec::try_from("user/repo; tag = v1.0").unwrap());
+        assert_eq!(lockfile.locked_commit(&tagged), None);
+    }
+
+    #[test]
+    fn test_remove() {
+        let mut lockfile = lockfile_with(
+            "repo",
+            LockedPlugin {
+                url: "https://github.com/user/repo.git".into(),
+                branch: None,
+                tag: None,
+                commit: "0123abcd".into(),
+            },
+        );
+        assert!(!lockfile.remove("other"));
+        assert!(lockfile.remove("repo"));
+        assert_eq!(lockfile.get("repo"), None);
+    }
+
+    #[test]
+    fn test_serialize() {
+        let lockfile = lockfile_with(
+            "repo",
+            LockedPlugin {
+                url: "https://github.com/user/repo.git".into(),
+                branch: None,
+                tag: None,
+                commit: "0123abcd".into(),
+            },
+        );
+        let json = serde_json::to_string(&lockfile).unwrap();
+        assert_eq!(
+            json,
+            r#"{"plugins":{"repo":{"url":"https://github.com/user/repo.git","commit":"0123abcd"}}}"#
+        );
+        assert_eq!(serde_json::from_str::<Lockfile>(&json).unwrap(), lockfile);
+    }
+}
@@ -1,9 +1,12 @@
+use std::process::exit;
+
 use ahiru_tpm::{
     args::{Action, Args},
-    cmd,
+    cmd, output, tmux,
 };
 use anyhow::Result;
 use clap::Parser;
+use serde_json::json;
 
 #[tokio::main]
 async fn main() -> Result<()> {
@@ -13,12 +16,38 @@ async fn main() -> Result<()> {
         .filter_level(log::LevelFilter::Warn)
         .init();
 
-    match args.action {
+    output::set_format(args.output);
+    if !args.configs.is_empty() {
+        tmux::set_config_paths(args.configs);
+    }
+    if let Some(plugins_dir) = args.plugins_dir {
+        tmux::set_plugins_dir(plugins_dir);
+    }
+
+    let result = match args.action {
         Action::Install(install_arga) => cmd::install(install_arga).await,
         Action::Update(update_args) => cmd::update(update_args).await,
-        Action::Load => cmd::load(),
-        Action::Clean => cmd::clean(),
-        Action::Sync => cmd::sync().await,
+        Action::Load(load_args) => cmd::load(load_args),
+        Action::Clean(clean_args) => cmd::clean(clean_args),
+        Action::Sync(sync_args) => cmd::sync(sync_args).await,
+        Action::Unload(unload_args) => cmd::unload(unload_args),
+        Action::Disable(toggle_args) => cmd::disable(toggle_args),
+        Action::Enable(toggle_args) => cmd::enable(toggle_args),
+        Action::Rollback(rollback_args) => cmd::rollback(rollback_args),
+        Action::Outdated => cmd::outdated().await,
         Action::Init => cmd::init(),
+        Action::List => cmd::list(),
+        Action::Check(check_args) => cmd::check(check_args),
+        Action::Log(log_args) => cmd::log(log_args),
+    };
+
+    // Report errors as JSON as well, so that scripts don't have to parse stderr
+    if let Err(err) = &result
+        && output::is_json()
+    {
+        output::print_json(&json!({ "error": format!("{err:#}") }))?;
+        exit(1);
     }
+
+    result
 }
@@ -0,0 +1,277 @@
+//! A persistent log of everything Ahiru-TPM did to plugins, stored as JSON lines in the state
+//! directory. The log is rotated once it gets too large.
+
+use std::{
+    fs::{self, OpenOptions},
+    io::Write,
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+use anyhow::{Context, Result};
+use log::warn;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    plugins::{action_result::ActionResult, load::LoadResult, unload::UnloadResult},
+    state,
+    utils::as_millis,
+};
+
+/// Size after which the log is rotated
+const MAX_SIZE: u64 = 1024 * 1024;
+
+/// Number of rotated logs that are kept in addition to the current one
+const MAX_ROTATED: usize = 3;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Status {
+    Success,
+    Failed,
+}
+
+/// A single operation on a plugin
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Entry {
+    pub timestamp: String,
+    pub command: String,
+    pub plugin: String,
+    pub status: Status,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub old_commit: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub new_commit: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub duration_ms: Option<f64>,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub stdout: String,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub stderr: String,
+}
+
+impl Entry {
+    pub fn new(command: &str, plugin: &str, error: Option<String>) -> Entry {
+        Entry {
+            timestamp: humantime::format_rfc3339_seconds(SystemTime::now()).to_string(),
+            command: command.to_owned(),
+            plugin: plugin.to_owned(),
+            status: match error {
+                None => Status::Success,
+                Some(_) => Status::Failed,
+            },
+            error,
+            old_commit: None,
+            new_commit: None,
+            duration_ms: None,
+            stdout: String::new(),
+            stderr: String::new(),
+        }
+    }
+
+    pub fn from_action(command: &str, result: &ActionResult) -> Entry {
+        let error = result.result.as_ref().err().map(|err| format!("{err:#}"));
+        Entry {
+            old_commit: result.old_commit.clone(),
+            new_commit: result.new_commit.clone(),
+            duration_ms: Some(as_millis(result.duration)),
+            stdout: result.stdout.clone(),
+            stderr: result.stderr.clone(),
+            ..Entry::new(command, result.plugin.name(), error)
+        }
+    }
+
+    pub fn from_load(command: &str, result: &LoadResult) -> Entry {
+        Entry {
+            duration_ms: Some(as_millis(result.duration)),
+            stdout: result.stdout.clone(),
+            stderr: result.stderr.clone(),
+            ..Entry::new(command, &result.plugin, result.error.clone())
+        }
+    }
+
+    /// An entry for a plugin directory that has been removed, possibly by moving it to the trash
+    pub fn removed(path: &Path, trash: Option<&Path>) -> Entry {
+        let name = path.file_name().unwrap_or_default().to_string_lossy();
+        let stdout = match trash {
+            Some(trash) => format!("Moved {} to {}", path.display(), trash.display()),
+            None => format!("Removed {}", path.display()),
+        };
+        Entry {
+            stdout,
+            ..Entry::new("clean", &name, None)
+        }
+    }
+
+    pub fn from_unload(result: &UnloadResult) -> Entry {
+        let mut stdout = format!("Reverted {} change(s) to tmux\n", result.reverted.len());
+        for change in &result.kept {
+            stdout += &format!("Kept {}, it has been changed since loading\n", change.name);
+        }
+        for change in &result.unattributed {
+            stdout += &format!(
+                "Did not revert {}, it could not be attributed to the plugin\n",
+                change.name
+            );
+        }
+        let error = result.is_failed().then(|| {
+            result
+                .failed
+                .iter()
+                .map(|failed| format!("Failed to revert {}: {}", failed.change.name, failed.error))
+                .collect::<Vec<_>>()
+                .join("\n")
+        });
+        Entry {
+            stdout,
+            ..Entry::new("unload", &result.plugin, error)
+        }
+    }
+
+    pub fn is_failed(&self) -> bool {
+        self.status == Status::Failed
+    }
+}
+
+fn get_log_path(dir: &Path, rotation: usize) -> PathBuf {
+    match rotation {
+        0 => dir.join("operations.jsonl"),
+        n => dir.join(format!("operations.{n}.jsonl")),
+    }
+}
+
+/// Append entries to the log. Failing to write the log doesn't fail the operations themselves,
+/// so errors are only reported as warnings.
+pub fn record(entries: impl IntoIterator<Item = Entry>) {
+    if let Err(err) = append(entries) {
+        warn!("Failed to write operation log: {err:#}");
+    }
+}
+
+fn append(entries: impl IntoIterator<Item = Entry>) -> Result<()> {
+    let mut content = String::new();
+    for entry in entries {
+        content += &serde_json::to_string(&entry).context("Failed to serialize log entry")?;
+        content += "\n";
+    }
+    if content.is_empty() {
+        return Ok(());
+    }
+
+    let dir = state::get_state_dir();
+    fs::create_dir_all(&dir)
+        .with_context(|| format!("Failed to create directory: {}", dir.display()))?;
+    rotate(&dir)?;
+
+    let path = get_log_path(&dir, 0);
+    OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&path)
+        .and_then(|mut file| file.write_all(content.as_bytes()))
+        .with_context(|| format!("Failed writing log file: {}", path.display()))
+}
+
+/// Move the current log aside once it exceeds `MAX_SIZE`, dropping the oldest rotated log
+fn rotate(dir: &Path) -> Result<()> {
+    let path = get_log_path(dir, 0);
+    if fs::metadata(&path).map_or(true, |metadata| metadata.len() < MAX_SIZE) {
+        return Ok(());
+    }
+
+    for rotation in (0..MAX_ROTATED).rev() {
+        let from = get_log_path(dir, rotation);
+        if from.exists() {
+            let to = get_log_path(dir, rotation + 1);
+            fs::rename(&from, &to)
+                .with_context(|| format!("Failed to rotate log file: {}", from.display()))?;
+        }
+    }
+    Ok(())
+}
+
+/// Read all entries of the log, oldest first. Lines that can't be parsed are skipped.
+pub fn read() -> Result<Vec<Entry>> {
+    read_from(&state::get_state_dir())
+}
+
+fn read_from(dir: &Path) -> Result<Vec<Entry>> {
+    let mut entries = vec![];
+
+    for rotation in (0..=MAX_ROTATED).rev() {
+        let path = get_log_path(dir, rotation);
+        if !path.exists() {
+            continue;
+        }
+
+        let content = fs::read_to_string(&path)
+            .with_context(|| format!("Failed reading log file: {}", path.display()))?;
+        entries.extend(
+            content
+                .lines()
+                .filter_map(|line| serde_json::from_str::<Entry>(line).ok()),
+        );
+    }
+
+    Ok(entries)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::setup_dir;
+
+    #[test]
+    fn test_entry_format() {
+        let entry = Entry {
+            old_commit: Some("a1823e7".into()),
+            new_commit: Some("b0eb8c3".into()),
+            stderr: "boom\n".into(),
+            ..Entry::new("update", "plugin", Some("Failed".into()))
+        };
+
+        let line = serde_json::to_string(&entry).unwrap();
+        assert!(!line.contains("stdout"));
+        assert!(!line.contains("duration_ms"));
+
+        let parsed: Entry = serde_json::from_str(&line).unwrap();
+        assert!(parsed.is_failed());
+        assert_eq!(parsed.plugin, "plugin");
+        assert_eq!(parsed.old_commit.as_deref(), Some("a1823e7"));
+        assert_eq!(parsed.stdout, "");
+        assert_eq!(parsed.stderr, "boom\n");
+    }
+
+    #[test]
+    fn test_rotate() {
+        let line = |plugin| serde_json::to_string(&Entry::new("load", plugin, None)).unwrap();
+        let dir = setup_dir(
+            "operation-log-rotation",
+            &[
+                ("operations.3.jsonl", &line("dropped")),
+                ("operations.2.jsonl", &line("oldest")),
+                ("operations.1.jsonl", &line("older")),
+                ("operations.jsonl", &line("old")),
+            ],
+        );
+
+        // The log is only rotated once it is too large
+        rotate(&dir).unwrap();
+        assert!(get_log_path(&dir, 0).exists());
+
+        let padding = " ".repeat(MAX_SIZE as usize);
+        let content = [line("old"), line("current"), padding].join("\n");
+        fs::write(get_log_path(&dir, 0), content).unwrap();
+        rotate(&dir).unwrap();
+
+        assert!(!get_log_path(&dir, 0).exists());
+        let plugins = read_from(&dir)
+            .unwrap()
+            .into_iter()
+            .map(|entry| entry.plugin)
+            .collect::<Vec<_>>();
+        assert_eq!(plugins, vec!["oldest", "older", "old", "current"]);
+    }
+}
@@ -0,0 +1,26 @@
+use anyhow::{Context, Result};
+use once_cell::sync::OnceCell;
+use serde::Serialize;
+
+use crate::args::OutputFormat;
+
+static OUTPUT_FORMAT: OnceCell<OutputFormat> = OnceCell::new();
+
+/// Set the output format for the whole process. Only the first call has an effect.
+pub fn set_format(format: OutputFormat) {
+    let _ = OUTPUT_FORMAT.set(format);
+}
+
+pub fn format() -> OutputFormat {
+    OUTPUT_FORMAT.get().copied().unwrap_or_default()
+}
+
+pub fn is_json() -> bool {
+    format() == OutputFormat::Json
+}
+
+pub fn print_json(value: &impl Serialize) -> Result<()> {
+    let json = serde_json::to_string_pretty(value).context("Failed to serialize output")?;
+    println!("{json}");
+    Ok(())
+}
@@ -1,15 +1,25 @@
-use std::{fmt::Display, path::PathBuf};
+use std::{
+    collections::{BTreeSet, HashMap},
+    fmt::Display,
+    path::PathBuf,
+    time::Duration,
+};
 
+use log::warn;
 use once_cell::sync::OnceCell;
 use url::Url;
 
-use crate::{attribute::Attribute, plugins, repo_url::RepoUrl, spec::Spec, tmux, utils};
+use crate::{
+    attribute::Attribute, condition, plugins, plugins::disable, repo_url::RepoUrl, spec::Spec,
+    tmux, utils,
+};
 
 pub struct Plugin {
     spec: Spec,
     // TODO: Once `std::cell::OnceCell::get_or_try_init` is stable replace `once_cell` crate with
     //       `std::cell`
     path: OnceCell<PathBuf>,
+    skip_reason: OnceCell<Option<String>>,
 }
 
 impl Plugin {
@@ -28,6 +38,15 @@ impl Plugin {
             .unwrap_or_else(|| self.spec.name())
     }
 
+    /// The directory of a plugin that is used from a local directory instead of being cloned
+    pub fn local_path(&self) -> Option<PathBuf> {
+        self.spec.url().local_path()
+    }
+
+    pub fn is_local(&self) -> bool {
+        matches!(self.spec.url(), RepoUrl::Local(_))
+    }
+
     pub fn is_installed(&self) -> bool {
         self.path().exists()
     }
@@ -41,6 +60,65 @@ impl Plugin {
         self.spec.branch()
     }
 
+    pub fn attributes(&self) -> &HashMap<Attribute, String> {
+        self.spec.attributes()
+    }
+
+    /// The tag the plugin is pinned to
+    pub fn tag(&self) -> Option<&str> {
+        self.spec
+            .attributes()
+            .get(&Attribute::Tag)
+            .map(String::as_str)
+    }
+
+    /// The commit the plugin is pinned to
+    pub fn rev(&self) -> Option<&str> {
+        self.spec
+            .attributes()
+            .get(&Attribute::Rev)
+            .map(String::as_str)
+    }
+
+    /// The reason why the plugin is skipped on this machine, if any of its activation conditions
+    /// (`os`, `host`, `env` or `if`) does not hold
+    pub fn skip_reason(&self) -> Option<&str> {
+        self.skip_reason
+            .get_or_init(|| condition::evaluate(self.spec.attributes()))
+            .as_deref()
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.skip_reason().is_none()
+    }
+
+    /// Whether the plugin is disabled with `enabled = false` in the tmux config
+    pub fn is_disabled_in_config(&self) -> bool {
+        self.spec
+            .attributes()
+            .get(&Attribute::Enabled)
+            .and_then(|s| utils::parse_bool(s))
+            == Some(false)
+    }
+
+    /// The reason why the plugin is disabled, if it is. Disabled plugins are installed and updated
+    /// as usual, but they are not loaded.
+    pub fn disable_reason(&self) -> Option<&'static str> {
+        self.disable_reason_in(&disable::get_disabled())
+    }
+
+    /// The reason why the plugin is disabled, given the names of the plugins that have been
+    /// disabled with `ahiru-tpm disable`
+    fn disable_reason_in(&self, disabled: &BTreeSet<String>) -> Option<&'static str> {
+        if self.is_disabled_in_config() {
+            Some("disabled in the tmux config (`enabled = false`)")
+        } else if disabled.contains(self.name()) {
+            Some("disabled with `ahiru-tpm disable`")
+        } else {
+            None
+        }
+    }
+
     pub fn parallel(&self) -> bool {
         self.spec
             .attributes()
@@ -48,6 +126,25 @@ impl Plugin {
             .and_then(|s| utils::parse_bool(s))
             .unwrap_or_else(plugins::do_parallel)
     }
+
+    /// The time after which loading the plugin is aborted. `None` disables the timeout.
+    pub fn timeout(&self) -> Option<Duration> {
+        let Some(val) = self.spec.attributes().get(&Attribute::Timeout) else {
+            return plugins::load_timeout();
+        };
+
+        match utils::parse_duration(val) {
+            Some(Duration::ZERO) => None,
+            Some(timeout) => Some(timeout),
+            None => {
+                warn!(
+                    r#"Invalid timeout "{val}" for plugin "{}". Falling back to `@tpm-load-timeout`"#,
+                    self.name()
+                );
+                plugins::load_timeout()
+            }
+        }
+    }
 }
 
 impl From<Spec> for Plugin {
@@ -55,6 +152,7 @@ impl From<Spec> for Plugin {
         Plugin {
             spec,
             path: OnceCell::new(),
+            skip_reason: OnceCell::new(),
         }
     }
 }
@@ -65,6 +163,50 @@ impl Display for Plugin {
         if let Some(branch) = self.branch() {
             write!(f, "#{branch}")?;
         };
+        if let Some(pin) = self.tag().or_else(|| self.rev()) {
+            write!(f, "@{pin}")?;
+        };
         write!(f, ")")
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn plugin(spec: &str) -> Plugin {
+        Plugin::from(Spec::try_from(spec).unwrap())
+    }
+
+    #[test]
+    fn test_is_disabled_in_config() {
+        assert!(plugin("user/a; enabled = false").is_disabled_in_config());
+        assert!(plugin("user/a; enabled = off").is_disabled_in_config());
+        assert!(!plugin("user/a; enabled = true").is_disabled_in_config());
+        assert!(!plugin("user/a").is_disabled_in_config());
+    }
+
+    #[test]
+    fn test_disable_reason() {
+        let disabled = BTreeSet::from(["a".to_owned(), "c".to_owned()]);
+
+        // The config takes precedence over `ahiru-tpm disable`
+        assert_eq!(
+            plugin("user/a; enabled = false").disable_reason_in(&disabled),
+            Some("disabled in the tmux config (`enabled = false`)")
+        );
+        assert_eq!(
+            plugin("user/b; enabled = false").disable_reason_in(&disabled),
+            Some("disabled in the tmux config (`enabled = false`)")
+        );
+        assert_eq!(
+            plugin("user/c; enabled = true").disable_reason_in(&disabled),
+            Some("disabled with `ahiru-tpm disable`")
+        );
+        assert_eq!(
+            plugin("user/x; alias = c").disable_reason_in(&disabled),
+            Some("disabled with `ahiru-tpm disable`")
+        );
+        assert_eq!(plugin("user/d").disable_reason_in(&disabled), None);
+    }
+}
@@ -1,12 +1,29 @@
-use std::io;
+use std::{
+    io,
+    time::{Duration, Instant},
+};
 
-use crate::plugin::Plugin;
+use anyhow::Result;
+use serde::{Serialize, Serializer, ser::SerializeStruct};
+
+use crate::{
+    git::{self, Commit},
+    plugin::Plugin,
+    utils,
+};
 
 pub struct ActionResult {
     pub plugin: Plugin,
     pub result: Result<(), io::Error>,
     pub stdout: String,
     pub stderr: String,
+    pub old_commit: Option<String>,
+    pub new_commit: Option<String>,
+    pub commits: Vec<Commit>,
+    pub duration: Duration,
+    /// Set when git succeeded, but a `build`, `post_install` or `post_update` command failed
+    /// afterwards. `result` holds the same error.
+    pub hook_error: Option<String>,
 }
 
 impl ActionResult {
@@ -16,6 +33,55 @@ impl ActionResult {
             result: output.0,
             stdout: output.1,
             stderr: output.2,
+            old_commit: None,
+            new_commit: None,
+            commits: vec![],
+            duration: Duration::ZERO,
+            hook_error: None,
         }
     }
+
+    /// Whether git succeeded, even if a hook command failed afterwards. The plugin is at
+    /// `new_commit` then.
+    pub fn git_succeeded(&self) -> bool {
+        self.result.is_ok() || self.hook_error.is_some()
+    }
+
+    /// Run an action and record how long it took
+    pub fn timed(action: impl FnOnce() -> Result<ActionResult>) -> Result<ActionResult> {
+        let start = Instant::now();
+        let mut result = action()?;
+        result.duration = start.elapsed();
+        Ok(result)
+    }
+
+    /// Record the commit before the action and read the commit the plugin is at after it
+    pub fn with_commits(mut self, old_commit: Option<String>) -> ActionResult {
+        self.old_commit = old_commit;
+        self.new_commit = git::head_commit(self.plugin.path()).ok();
+        self
+    }
+}
+
+impl Serialize for ActionResult {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let (status, error) = match &self.result {
+            Ok(()) => ("success", None),
+            Err(err) => ("failed", Some(err.to_string())),
+        };
+
+        let mut state = serializer.serialize_struct("ActionResult", 11)?;
+        state.serialize_field("plugin", self.plugin.name())?;
+        state.serialize_field("url", self.plugin.url().as_str())?;
+        state.serialize_field("status", status)?;
+        state.serialize_field("error", &error)?;
+        state.serialize_field("hook_error", &self.hook_error)?;
+        state.serialize_field("stdout", &self.stdout)?;
+        state.serialize_field("stderr", &self.stderr)?;
+        state.serialize_field("old_commit", &self.old_commit)?;
+        state.serialize_field("new_commit", &self.new_commit)?;
+        state.serialize_field("commits", &self.commits)?;
+        state.serialize_field("duration_ms", &utils::as_millis(self.duration))?;
+        state.end()
+    }
 }
@@ -0,0 +1,390 @@
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+};
+
+use anyhow::Error;
+use cmd_lib::run_fun;
+use colored::Colorize;
+use glob::glob;
+use is_executable::IsExecutable;
+use itertools::Itertools;
+use rustix::fs::{Access, access};
+use serde::Serialize;
+
+use crate::{
+    diagnostic::{Diagnostic, Location},
+    key_bindings,
+    plugin::Plugin,
+    prefix_lines::PrefixLines,
+    spec::Spec,
+    tmux,
+};
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Status {
+    Ok,
+    Warning,
+    Error,
+}
+
+/// The outcome of a single check
+#[derive(Serialize)]
+pub struct Check {
+    pub check: &'static str,
+    pub status: Status,
+    pub message: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub location: Option<Location>,
+    /// Rendered in place of the message in text output
+    #[serde(skip)]
+    diagnostic: Option<Diagnostic>,
+}
+
+impl Check {
+    fn new(check: &'static str, status: Status, message: impl Into<String>) -> Check {
+        Check {
+            check,
+            status,
+            message: message.into(),
+            location: None,
+            diagnostic: None,
+        }
+    }
+
+    fn ok(check: &'static str, message: impl Into<String>) -> Check {
+        Check::new(check, Status::Ok, message)
+    }
+
+    fn warning(check: &'static str, message: impl Into<String>) -> Check {
+        Check::new(check, Status::Warning, message)
+    }
+
+    fn error(check: &'static str, message: impl Into<String>) -> Check {
+        Check::new(check, Status::Error, message)
+    }
+
+    fn from_diagnostic(check: &'static str, status: Status, diagnostic: Diagnostic) -> Check {
+        Check {
+            check,
+            status,
+            message: diagnostic.message.clone(),
+            location: Some(diagnostic.location.clone()),
+            diagnostic: Some(diagnostic),
+        }
+    }
+
+    /// Create a check from an error. Errors pointing at a location in a config file keep it.
+    fn from_error(check: &'static str, err: Error) -> Check {
+        match err.downcast_ref::<Diagnostic>() {
+            Some(diagnostic) => Check::from_diagnostic(check, Status::Error, diagnostic.clone()),
+            None => Check::error(check, format!("{err:#}")),
+        }
+    }
+}
+
+/// Validate the configuration and the environment without changing anything
+pub fn check() -> Vec<Check> {
+    let mut checks = vec![];
+
+    let specs = check_configs(&mut checks);
+    check_duplicates(&specs, &mut checks);
+    check_git(&mut checks);
+    check_tmux(&mut checks);
+    check_plugins_dir(&mut checks);
+
+    let plugins: Vec<Plugin> = specs.into_iter().map(|(spec, _)| spec.into()).collect();
+    check_load_order(&plugins, &mut checks);
+    check_plugins(&plugins, &mut checks);
+    check_orphans(&plugins, &mut checks);
+
+    checks
+}
+
+/// Parse all configs and collect the specs defined in them. Specs defined via the legacy
+/// `@tpm_plugins` option have no location.
+fn check_configs(checks: &mut Vec<Check>) -> Vec<(Spec, Option<Location>)> {
+    let configs = tmux::get_config_paths();
+    if configs.is_empty() {
+        checks.push(Check::error(
+            "config",
+            "Failed to find any tmux config files",
+        ));
+    }
+
+    let mut specs = vec![];
+    for config in configs {
+        let mut warnings = vec![];
+        match super::get_specs_from_config(&config, &mut warnings) {
+            Ok(config_specs) => {
+                checks.push(Check::ok(
+                    "config",
+                    format!("{} ({} plugins)", config.display(), config_specs.len()),
+                ));
+                specs.extend(
+                    config_specs
+                        .into_iter()
+                        .map(|(spec, location)| (spec, Some(location))),
+                );
+            }
+            Err(err) => checks.push(Check::from_error("config", err)),
+        }
+
+        checks.extend(
+            warnings
+                .into_iter()
+                .map(|warning| Check::from_diagnostic("config", Status::Warning, warning)),
+        );
+    }
+
+    match super::load_legacy_specs() {
+        Ok(legacy_specs) => specs.extend(legacy_specs.into_iter().map(|spec| (spec, None))),
+        Err(err) => checks.push(Check::from_error("config", err)),
+    }
+
+    specs
+}
+
+fn check_duplicates(specs: &[(Spec, Option<Location>)], checks: &mut Vec<Check>) {
+    let mut locations_by_name: HashMap<String, Vec<Option<&Location>>> = HashMap::new();
+    for (spec, location) in specs {
+        // Skipped plugins may share their name with an active one
+        let plugin = Plugin::from(spec.clone());
+        if !plugin.is_active() {
+            continue;
+        }
+        locations_by_name
+            .entry(plugin.name().to_owned())
+            .or_default()
+            .push(location.as_ref());
+    }
+
+    let duplicates = locations_by_name
+        .into_iter()
+        .filter(|(_, locations)| locations.len() > 1)
+        .sorted_by(|(a, _), (b, _)| a.cmp(b))
+        .collect_vec();
+
+    if duplicates.is_empty() {
+        checks.push(Check::ok("duplicates", "All plugin names are unique"));
+    }
+
+    for (name, locations) in duplicates {
+        let locations = locations
+            .iter()
+            .map(|location| match location {
+                Some(location) => location.to_string(),
+                None => "@tpm_plugins".into(),
+            })
+            .join(", ");
+        checks.push(Check::error(
+            "duplicates",
+            format!(
+                r#"More than one plugin with the name "{name}" has been specified: {locations}"#
+            ),
+        ));
+    }
+}
+
+fn check_git(checks: &mut Vec<Check>) {
+    checks.push(match run_fun!(git --version 2>/dev/null) {
+        Ok(version) => Check::ok("git", version),
+        Err(_) => Check::error("git", "`git` is not installed"),
+    });
+}
+
+fn check_tmux(checks: &mut Vec<Check>) {
+    let Ok(version) = run_fun!(tmux -V 2>/dev/null) else {
+        checks.push(Check::error("tmux", "`tmux` is not installed"));
+        return;
+    };
+
+    if !tmux::is_tmux_running() {
+        checks.push(Check::warning(
+            "tmux",
+            format!("{version} is installed but not running, key bindings are not checked"),
+        ));
+        return;
+    }
+    checks.push(Check::ok("tmux", format!("{version} is running")));
+
+    let keys = key_bindings::keys();
+    for (action, key) in &keys {
+        let clashing_actions = keys
+            .iter()
+            .filter(|(other, other_key)| other != action && other_key == key)
+            .map(|(other, _)| *other)
+            .collect_vec();
+        if !clashing_actions.is_empty() {
+            checks.push(Check::error(
+                "key-bindings",
+                format!(
+                    "Key `{key}` of `{action}` is also used for `{}`",
+                    clashing_actions.join("`, `")
+                ),
+            ));
+            continue;
+        }
+
+        match tmux::get_key_binding(key) {
+            Some(binding) if !binding.contains("ahiru-tpm") => {
+                checks.push(Check::warning(
+                    "key-bindings",
+                    format!("Key `{key}` of `{action}` is already bound: {binding}"),
+                ));
+            }
+            _ => checks.push(Check::ok("key-bindings", format!("`{action}` on `{key}`"))),
+        }
+    }
+}
+
+fn check_load_order(plugins: &[Plugin], checks: &mut Vec<Check>) {
+    let active = plugins
+        .iter()
+        .filter(|plugin| plugin.is_active())
+        .collect_vec();
+
+    checks.push(match super::load_order::validate(&active) {
+        Ok(()) => Check::ok("load-order", "Load order can be resolved"),
+        Err(err) => Check::from_error("load-order", err),
+    });
+}
+
+fn check_plugins_dir(checks: &mut Vec<Check>) {
+    let path = tmux::get_plugins_dir();
+
+    if path.exists() && !path.is_dir() {
+        checks.push(Check::error(
+            "plugins-dir",
+            format!("Not a directory: {}", path.display()),
+        ));
+        return;
+    }
+
+    // A missing plugins directory is created on install, so its closest existing parent needs to
+    // be writable
+    let existing = path
+        .ancestors()
+        .find(|dir| dir.exists())
+        .unwrap_or(Path::new("/"));
+
+    checks.push(if is_writable(existing) {
+        Check::ok("plugins-dir", format!("{} is writable", path.display()))
+    } else {
+        Check::error(
+            "plugins-dir",
+            format!("{} is not writable", existing.display()),
+        )
+    });
+}
+
+fn is_writable(dir: &Path) -> bool {
+    access(dir, Access::WRITE_OK).is_ok()
+}
+
+fn check_plugins(plugins: &[Plugin], checks: &mut Vec<Check>) {
+    for plugin in plugins {
+        if let Some(reason) = plugin.skip_reason() {
+            checks.push(Check::ok(
+                "plugins",
+                format!("{} is skipped: {reason}", plugin.name()),
+            ));
+            continue;
+        }
+
+        if !plugin.is_installed() {
+            checks.push(Check::warning(
+                "plugins",
+                format!("{} is not installed", plugin.name()),
+            ));
+            continue;
+        }
+
+        if let Some(reason) = plugin.disable_reason() {
+            checks.push(Check::ok(
+                "plugins",
+                format!("{} is disabled: {reason}", plugin.name()),
+            ));
+            continue;
+        }
+
+        let init_files = get_init_files(plugin.path());
+        if init_files.iter().any(|file| file.is_executable()) {
+            checks.push(Check::ok(
+                "plugins",
+                format!("{} is installed", plugin.name()),
+            ));
+        } else if init_files.is_empty() {
+            checks.push(Check::warning(
+                "plugins",
+                format!("{} has no `*.tmux` file to load", plugin.name()),
+            ));
+        } else {
+            checks.push(Check::warning(
+                "plugins",
+                format!(
+                    "{} has no executable `*.tmux` file to load: {}",
+                    plugin.name(),
+                    init_files.iter().map(|file| file.display()).join(", ")
+                ),
+            ));
+        }
+    }
+}
+
+fn get_init_files(path: &Path) -> Vec<PathBuf> {
+    let Some(path) = path.to_str() else {
+        return vec![];
+    };
+
+    glob(&format!("{path}/*.tmux"))
+        .map(|paths| paths.flatten().filter(|path| path.is_file()).collect())
+        .unwrap_or_default()
+}
+
+fn check_orphans(plugins: &[Plugin], checks: &mut Vec<Check>) {
+    match super::clean::get_orphans(plugins) {
+        Ok(orphans) if orphans.is_empty() => {
+            checks.push(Check::ok("orphans", "No orphaned plugin directories"))
+        }
+        Ok(orphans) => checks.extend(orphans.into_iter().map(|orphan| {
+            Check::warning(
+                "orphans",
+                format!("{} is not used by any plugin", orphan.display()),
+            )
+        })),
+        Err(err) => checks.push(Check::from_error("orphans", err)),
+    }
+}
+
+pub fn print_checks(checks: &[Check]) {
+    for check in checks {
+        let status = match check.status {
+            Status::Ok => "ok".bold().green(),
+            Status::Warning => "warning".bold().yellow(),
+            Status::Error => "error".bold().red(),
+        };
+        let label = format!("[{}]", check.check).dimmed();
+
+        match &check.diagnostic {
+            Some(diagnostic) => {
+                println!("{status:>7} {label}");
+                println!("{}", diagnostic.to_string().prefix_lines("        "));
+            }
+            None => println!("{status:>7} {label} {}", check.message),
+        }
+    }
+
+    let count = |status| checks.iter().filter(|check| check.status == status).count();
+    println!();
+    println!(
+        "{}",
+        format!(
+            "==> {} errors, {} warnings",
+            count(Status::Error),
+            count(Status::Warning)
+        )
+        .bold()
+    );
+}
@@ -1,22 +1,273 @@
-use std::{collections::HashSet, fs::remove_dir_all};
+use std::{
+    collections::HashSet,
+    fs::{self, remove_file},
+    io::{self, IsTerminal, Write},
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
 
-use anyhow::{Context, Result};
+use anyhow::{Context, Result, bail};
+use cmd_lib::run_cmd;
+use colored::Colorize;
+use serde::Serialize;
 
-use crate::tmux::{self};
+use crate::{
+    git,
+    lockfile::Lockfile,
+    operation_log::{self, Entry},
+    output,
+    plugin::Plugin,
+    tmux::{self},
+};
 
-pub fn clean() -> Result<()> {
-    let plugin_set: HashSet<_> = super::get_plugins()?
-        .into_iter()
-        .map(|plugin| {
-            Ok(plugin
-                .path()
-                .to_str()
-                .context("Path is not valid UTF-8")?
-                .to_owned())
+use super::unload::{self, UnloadResult};
+
+/// How `clean` removes orphaned plugin directories
+#[derive(Debug, Default, Clone, Copy)]
+pub struct CleanOptions {
+    /// Only report what would be removed
+    pub dry_run: bool,
+    /// Don't ask for confirmation
+    pub yes: bool,
+    /// Also remove plugins with uncommitted changes or unpushed commits
+    pub force: bool,
+    /// Revert the changes removed plugins made to the running tmux server
+    pub unload: bool,
+}
+
+#[derive(Serialize, Default)]
+pub struct CleanResult {
+    /// Removed plugin directories, or the ones that would be removed in a dry run
+    pub removed: Vec<Removed>,
+    /// Orphaned plugin directories that are kept because they contain local work
+    pub kept: Vec<Kept>,
+    /// Removed plugins whose changes to tmux have been reverted
+    pub unloaded: Vec<UnloadResult>,
+}
+
+#[derive(Serialize)]
+pub struct Removed {
+    pub path: PathBuf,
+    /// Where the directory has been moved to. Links to local plugins are removed without being
+    /// moved to the trash.
+    pub trash: Option<PathBuf>,
+}
+
+#[derive(Serialize)]
+pub struct Kept {
+    pub path: PathBuf,
+    pub reason: String,
+}
+
+/// Get the directory removed plugins are moved to
+pub fn get_trash_dir() -> PathBuf {
+    xdir::data()
+        .expect("XDG paths should be possible to build")
+        .join("ahiru-tpm/trash")
+}
+
+pub fn clean(options: CleanOptions) -> Result<CleanResult> {
+    let plugins = super::get_all_plugins()?;
+    let mut result = CleanResult::default();
+    let mut to_remove = vec![];
+
+    for path in get_orphans(&plugins)? {
+        // Only the link of a local plugin is removed, so nothing can get lost
+        let local_work = if path.is_symlink() || options.force {
+            None
+        } else {
+            git::local_work(&path)
+                .unwrap_or_else(|err| Some(format!("could not be checked for local work: {err:#}")))
+        };
+
+        match local_work {
+            Some(reason) => result.kept.push(Kept { path, reason }),
+            None => to_remove.push(path),
+        }
+    }
+
+    if !output::is_json() {
+        print_plan(&to_remove, &result.kept, options.dry_run);
+    }
+
+    if options.dry_run {
+        result.removed = to_remove
+            .into_iter()
+            .map(|path| Removed { path, trash: None })
+            .collect();
+        return Ok(result);
+    }
+
+    if to_remove.is_empty() || !confirm(options.yes)? {
+        return Ok(result);
+    }
+
+    for path in to_remove {
+        let trash = if path.is_symlink() {
+            remove_file(&path)?;
+            eprintln!("-> Removed {}", path.display());
+            None
+        } else {
+            let trash = move_to_trash(&path)?;
+            eprintln!("-> Moved {} to {}", path.display(), trash.display());
+            Some(trash)
+        };
+        result.removed.push(Removed { path, trash });
+    }
+
+    unlock_removed(&result.removed)?;
+
+    operation_log::record(
+        result
+            .removed
+            .iter()
+            .map(|removed| Entry::removed(&removed.path, removed.trash.as_deref())),
+    );
+
+    if options.unload {
+        result.unloaded = unload_removed(&result.removed)?;
+    }
+
+    Ok(result)
+}
+
+/// Drop the lockfile entries of the removed plugins
+fn unlock_removed(removed: &[Removed]) -> Result<()> {
+    let mut lockfile = Lockfile::load()?;
+    let mut changed = false;
+    for name in removed_names(removed) {
+        changed |= lockfile.remove(&name);
+    }
+    if changed {
+        lockfile.save()?;
+    }
+    Ok(())
+}
+
+/// Unload the removed plugins, if tmux is running
+fn unload_removed(removed: &[Removed]) -> Result<Vec<UnloadResult>> {
+    if !tmux::is_tmux_running() {
+        eprintln!("WARN: Tmux is not running, plugins are not unloaded.");
+        return Ok(vec![]);
+    }
+
+    let names = removed_names(removed);
+    let results = unload::unload(&names)?;
+
+    if !output::is_json() {
+        unload::print_results(&results);
+    }
+    Ok(results)
+}
+
+fn removed_names(removed: &[Removed]) -> Vec<String> {
+    removed
+        .iter()
+        .map(|removed| {
+            removed
+                .path
+                .file_name()
+                .unwrap_or_default()
+                .to_string_lossy()
+                .into_owned()
         })
-        .collect::<Result<HashSet<_>>>()?;
+        .collect()
+}
+
+fn print_plan(to_remove: &[PathBuf], kept: &[Kept], dry_run: bool) {
+    if !to_remove.is_empty() {
+        let verb = if dry_run { "would be" } else { "will be" };
+        eprintln!(
+            "{}",
+            format!("==> The following plugin directories {verb} moved to the trash:").bold()
+        );
+        for path in to_remove {
+            if path.is_symlink() {
+                eprintln!("    {} (only the link is removed)", path.display());
+            } else {
+                eprintln!("    {}", path.display());
+            }
+        }
+    }
+
+    if !kept.is_empty() {
+        eprintln!(
+            "{}",
+            "==> Keeping plugin directories with local work (use `--force` to remove them):"
+                .bold()
+                .yellow()
+        );
+        for kept in kept {
+            eprintln!("    {} {}", kept.path.display(), kept.reason.dimmed());
+        }
+    }
+}
+
+/// Ask whether to proceed. Without a terminal to ask on, `--yes` is required.
+fn confirm(yes: bool) -> Result<bool> {
+    if yes {
+        return Ok(true);
+    }
+
+    if !io::stdin().is_terminal() {
+        bail!(
+            "Refusing to remove plugin directories without confirmation. Pass `--yes` to remove them."
+        );
+    }
+
+    eprint!("Proceed? [y/N] ");
+    io::stderr().flush()?;
+
+    let mut answer = String::new();
+    io::stdin().read_line(&mut answer)?;
+
+    let proceed = matches!(answer.trim().to_lowercase().as_str(), "y" | "yes");
+    if !proceed {
+        eprintln!("-> Aborted, nothing has been removed");
+    }
+    Ok(proceed)
+}
 
-    let plugin_dir = tmux::get_plugins_dir();
+/// Move a plugin directory into the trash, where it can be restored from. Returns its path in the
+/// trash.
+fn move_to_trash(path: &Path) -> Result<PathBuf> {
+    let trash_dir = get_trash_dir();
+    fs::create_dir_all(&trash_dir)
+        .with_context(|| format!("Failed to create directory: {}", trash_dir.display()))?;
+
+    let name = path.file_name().unwrap_or_default().to_string_lossy();
+    let timestamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+    let target = trash_dir.join(format!("{name}-{timestamp}"));
+
+    // Renaming fails if the trash is on another file system than the plugins directory
+    if fs::rename(path, &target).is_err() {
+        run_cmd!(mv $path $target)
+            .with_context(|| format!("Failed to move {} to the trash", path.display()))?;
+    }
+
+    Ok(target)
+}
+
+/// Get all directories (and links to local plugins) in the plugins dir that do not belong to a
+/// plugin in the tmux config
+pub fn get_orphans(plugins: &[Plugin]) -> Result<Vec<PathBuf>> {
+    find_orphans(&tmux::get_plugins_dir(), plugins)
+}
+
+fn find_orphans(plugin_dir: &Path, plugins: &[Plugin]) -> Result<Vec<PathBuf>> {
+    let plugin_set: HashSet<_> = plugins
+        .iter()
+        .map(|plugin| plugin_dir.join(plugin.name()))
+        .collect();
+
+    if !plugin_dir.exists() {
+        return Ok(vec![]);
+    }
+
+    let mut orphans = vec![];
 
     // List entries in plugin_dir
     for entry in plugin_dir
@@ -26,18 +277,43 @@ pub fn clean() -> Result<()> {
         let entry = entry?;
         let file_type = entry.file_type()?;
 
-        if !file_type.is_dir() {
+        if !file_type.is_dir() && !file_type.is_symlink() {
             continue;
         }
 
         let path = entry.path();
-        let path_str = path.to_str().context("Path is not valid UTF-8")?;
-
-        if !plugin_set.contains(path_str) {
-            eprintln!("-> Removing {path_str}");
-            remove_dir_all(path)?;
+        if !plugin_set.contains(&path) {
+            orphans.push(path);
         }
     }
 
-    Ok(())
+    Ok(orphans)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{spec::Spec, test_utils::setup_dir};
+
+    use super::*;
+
+    #[test]
+    fn test_find_orphans() {
+        let dir = setup_dir(
+            "clean-orphans",
+            &[
+                ("enabled/init.tmux", ""),
+                ("disabled/init.tmux", ""),
+                ("orphan/init.tmux", ""),
+                ("file", ""),
+            ],
+        );
+        let plugins = ["user/enabled", "user/disabled; enabled = false"]
+            .map(|spec| Plugin::from(Spec::try_from(spec).unwrap()));
+
+        // Disabled plugins stay installed
+        assert_eq!(
+            find_orphans(&dir, &plugins).unwrap(),
+            vec![dir.join("orphan")]
+        );
+    }
 }
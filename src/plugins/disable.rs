@@ -0,0 +1,93 @@
+use std::collections::BTreeSet;
+
+use anyhow::{Result, bail};
+use cached::proc_macro::cached;
+use colored::Colorize;
+use log::warn;
+
+use crate::{output, plugin::Plugin, state};
+
+const STATE_NAME: &str = "disabled";
+
+/// Names of the plugins that have been disabled with `ahiru-tpm disable`
+type DisabledState = BTreeSet<String>;
+
+/// Get the names of the plugins that have been disabled with `ahiru-tpm disable`
+#[cached]
+pub fn get_disabled() -> DisabledState {
+    state::load(STATE_NAME).unwrap_or_else(|err| {
+        warn!("Failed to load disabled plugins: {err:#}");
+        DisabledState::new()
+    })
+}
+
+/// Disable plugins, so that they are not loaded anymore while they stay installed
+pub fn disable(names: &[String]) -> Result<()> {
+    ensure_configured(names)?;
+
+    let mut disabled: DisabledState = state::load(STATE_NAME)?;
+    for name in names {
+        let newly_disabled = disabled.insert(name.to_owned());
+        if !output::is_json() {
+            match newly_disabled {
+                true => eprintln!("-> Disabled {}", name.bold()),
+                false => eprintln!("-> {} is disabled already", name.bold()),
+            }
+        }
+    }
+    state::save(STATE_NAME, &disabled)?;
+
+    if !output::is_json() {
+        eprintln!(
+            "{}",
+            "==> Plugins stay loaded until tmux is restarted. Run `ahiru-tpm unload` to unload them right away."
+                .dimmed()
+        );
+    }
+    Ok(())
+}
+
+/// Enable plugins that have been disabled with `ahiru-tpm disable`
+pub fn enable(names: &[String]) -> Result<()> {
+    let plugins = ensure_configured(names)?;
+
+    let mut disabled: DisabledState = state::load(STATE_NAME)?;
+    for name in names {
+        let was_disabled = disabled.remove(name);
+        if output::is_json() {
+            continue;
+        }
+        match was_disabled {
+            true => eprintln!("-> Enabled {}", name.bold()),
+            false => eprintln!("-> {} is not disabled", name.bold()),
+        }
+    }
+    state::save(STATE_NAME, &disabled)?;
+
+    for plugin in plugins {
+        if plugin.is_disabled_in_config() {
+            warn!(
+                r#"Plugin "{}" stays disabled, because it is disabled in the tmux config (`enabled = false`)"#,
+                plugin.name()
+            );
+        }
+    }
+    Ok(())
+}
+
+/// Make sure that plugins with the given names are configured. Returns these plugins.
+fn ensure_configured(names: &[String]) -> Result<Vec<Plugin>> {
+    let plugins = super::get_all_plugins()?;
+
+    if let Some(name) = names
+        .iter()
+        .find(|name| !plugins.iter().any(|plugin| plugin.name() == *name))
+    {
+        bail!("Unknown plugin name: {}", name);
+    }
+
+    Ok(plugins
+        .into_iter()
+        .filter(|plugin| names.iter().any(|name| name == plugin.name()))
+        .collect())
+}
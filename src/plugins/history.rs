@@ -0,0 +1,167 @@
+use anyhow::Result;
+use colored::Colorize;
+
+use crate::{
+    operation_log::{self, Entry, Status},
+    prefix_lines::PrefixLines,
+};
+
+/// Get the most recent operations, oldest first. Only operations on plugins with the given names
+/// are returned if any are given.
+pub fn history<T: AsRef<str>>(names: &[T], failed: bool, limit: usize) -> Result<Vec<Entry>> {
+    Ok(filter(operation_log::read()?, names, failed, limit))
+}
+
+fn filter<T: AsRef<str>>(
+    entries: Vec<Entry>,
+    names: &[T],
+    failed: bool,
+    limit: usize,
+) -> Vec<Entry> {
+    let entries = entries
+        .into_iter()
+        .filter(|entry| names.is_empty() || names.iter().any(|name| name.as_ref() == entry.plugin))
+        .filter(|entry| !failed || entry.is_failed())
+        .collect::<Vec<_>>();
+
+    let skip = entries.len().saturating_sub(limit);
+    entries.into_iter().skip(skip).collect()
+}
+
+/// Get the most recent failed operation on any of the plugins with the given names
+pub fn last_failure<T: AsRef<str>>(names: &[T]) -> Result<Option<Entry>> {
+    Ok(history(names, true, 1)?.pop())
+}
+
+pub fn print_history(entries: &[Entry]) {
+    if entries.is_empty() {
+        println!("    (none)");
+    }
+
+    let width = entries
+        .iter()
+        .map(|entry| entry.plugin.len())
+        .max()
+        .unwrap_or_default();
+
+    for entry in entries {
+        let status = match entry.status {
+            Status::Success => "ok    ".bold().green(),
+            Status::Failed => "failed".bold().red(),
+        };
+        let commits = match (&entry.old_commit, &entry.new_commit) {
+            (Some(old), Some(new)) if old != new => format!("{} -> {}", short(old), short(new)),
+            (_, Some(new)) => short(new).to_owned(),
+            _ => String::new(),
+        };
+
+        println!(
+            "{} {:<8} {} {status} {:>9} {}",
+            entry.timestamp.dimmed(),
+            entry.command,
+            format!("{:<width$}", entry.plugin).bold(),
+            entry.duration_ms.map(format_duration).unwrap_or_default(),
+            commits.dimmed(),
+        );
+    }
+}
+
+/// Print an operation including its error and output
+pub fn print_entry(entry: &Entry) {
+    let status = match entry.status {
+        Status::Success => "success".bold().green(),
+        Status::Failed => "failed".bold().red(),
+    };
+    println!("{} ({status})", entry.plugin.bold());
+
+    print_field("time", &entry.timestamp);
+    print_field("command", &entry.command);
+    print_field("old commit", entry.old_commit.as_deref().unwrap_or("-"));
+    print_field("new commit", entry.new_commit.as_deref().unwrap_or("-"));
+    print_field(
+        "duration",
+        &entry.duration_ms.map(format_duration).unwrap_or_default(),
+    );
+    if let Some(error) = &entry.error {
+        print_field("error", error);
+    }
+
+    if !entry.stdout.is_empty() || !entry.stderr.is_empty() {
+        println!();
+    }
+    if !entry.stdout.is_empty() {
+        println!(
+            "{}",
+            entry
+                .stdout
+                .prefix_lines(&"  out> ".bold().green().to_string())
+        );
+    }
+    if !entry.stderr.is_empty() {
+        println!(
+            "{}",
+            entry
+                .stderr
+                .prefix_lines(&"  err-out> ".bold().red().to_string())
+        );
+    }
+}
+
+fn print_field(name: &str, value: &str) {
+    let value = if value.is_empty() { "-" } else { value };
+    let name = format!("{:<11}", format!("{name}:"));
+    println!("    {} {value}", name.dimmed());
+}
+
+fn short(commit: &str) -> &str {
+    &commit[..commit.len().min(7)]
+}
+
+fn format_duration(millis: f64) -> String {
+    if millis < 1000.0 {
+        format!("{millis:.0}ms")
+    } else {
+        format!("{:.1}s", millis / 1000.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn plugins(entries: &[Entry]) -> Vec<(&str, bool)> {
+        entries
+            .iter()
+            .map(|entry| (entry.plugin.as_str(), entry.is_failed()))
+            .collect()
+    }
+
+    #[test]
+    fn test_filter() {
+        let entries = vec![
+            Entry::new("install", "a", None),
+            Entry::new("install", "b", Some("Failed".into())),
+            Entry::new("update", "a", Some("Failed".into())),
+            Entry::new("update", "b", None),
+            Entry::new("load", "c", None),
+        ];
+        let no_names: &[&str] = &[];
+
+        assert_eq!(
+            plugins(&filter(entries.clone(), no_names, false, 3)),
+            vec![("a", true), ("b", false), ("c", false)]
+        );
+        assert_eq!(
+            plugins(&filter(entries.clone(), &["a", "c"], false, 20)),
+            vec![("a", false), ("a", true), ("c", false)]
+        );
+        assert_eq!(
+            plugins(&filter(entries.clone(), no_names, true, 20)),
+            vec![("b", true), ("a", true)]
+        );
+        assert_eq!(
+            plugins(&filter(entries, &["b"], true, 1)),
+            vec![("b", true)]
+        );
+    }
+}
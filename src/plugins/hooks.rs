@@ -0,0 +1,144 @@
+use std::{collections::BTreeSet, io};
+
+use anyhow::{Context, Result};
+use cmd_lib::{FunChildren, spawn_with_output};
+use log::warn;
+
+use crate::{attribute::Attribute, state};
+
+use super::action_result::ActionResult;
+
+const PENDING_STATE_NAME: &str = "pending-hooks";
+
+/// Names of the plugins whose update hooks failed. They are run again on the next update, even if
+/// the plugin has not changed since.
+type PendingHooks = BTreeSet<String>;
+
+/// The action after which hooks are run
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Hook {
+    Install,
+    Update,
+}
+
+impl Hook {
+    /// The attributes holding the commands to run, in the order they are run in. `build` runs
+    /// after both, installs and updates.
+    fn attributes(self) -> [Attribute; 2] {
+        match self {
+            Hook::Install => [Attribute::Build, Attribute::PostInstall],
+            Hook::Update => [Attribute::Build, Attribute::PostUpdate],
+        }
+    }
+}
+
+/// Run the hook commands of a plugin in its directory after it has been installed or updated
+/// successfully. Their output is appended to the result and a failing command fails it.
+pub fn run(mut result: ActionResult, hook: Hook) -> Result<ActionResult> {
+    if result.result.is_err() {
+        return Ok(result);
+    }
+
+    for attribute in hook.attributes() {
+        let Some(command) = result.plugin.attributes().get(&attribute).cloned() else {
+            continue;
+        };
+        let path = result.plugin.path().to_owned();
+
+        let mut proc: FunChildren = spawn_with_output!(
+            bash -c "
+                cd $path || exit 1;
+                $command
+            "
+        )
+        .context(format!(
+            r#"Failed to spawn subprocess for `{attribute}` of "{}""#,
+            result.plugin
+        ))?;
+        let (status, stdout, stderr) = proc.wait_with_all();
+
+        append_output(&mut result.stdout, &stdout);
+        append_output(&mut result.stderr, &stderr);
+
+        if let Err(err) = status {
+            let error = format!("`{attribute}` command failed: {err}");
+            result.result = Err(io::Error::other(error.clone()));
+            result.hook_error = Some(error);
+            break;
+        }
+    }
+
+    Ok(result)
+}
+
+/// Append the output of a command to the output collected so far, on a new line
+pub fn append_output(output: &mut String, addition: &str) {
+    if !output.is_empty() && !output.ends_with('\n') && !addition.is_empty() {
+        output.push('\n');
+    }
+    output.push_str(addition);
+}
+
+/// Whether the update hooks of a plugin failed last time and need to run again
+pub fn is_pending(name: &str) -> bool {
+    let pending: PendingHooks = state::load(PENDING_STATE_NAME).unwrap_or_else(|err| {
+        warn!("Failed to load pending hooks: {err:#}");
+        PendingHooks::new()
+    });
+    pending.contains(name)
+}
+
+/// Remember the plugins whose update hooks failed and forget the ones that have been updated
+/// successfully
+pub fn record_pending(results: &[ActionResult]) -> Result<()> {
+    let mut pending: PendingHooks = state::load(PENDING_STATE_NAME)?;
+    update_pending(&mut pending, results);
+    state::save(PENDING_STATE_NAME, &pending)
+}
+
+fn update_pending(pending: &mut PendingHooks, results: &[ActionResult]) {
+    for result in results {
+        let name = result.plugin.name();
+        if result.hook_error.is_some() {
+            pending.insert(name.to_owned());
+        } else if result.result.is_ok() {
+            pending.remove(name);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{plugin::Plugin, spec::Spec};
+
+    use super::*;
+
+    fn result(name: &str, error: Option<&str>, hook_error: Option<&str>) -> ActionResult {
+        let plugin = Plugin::from(Spec::try_from(format!("user/{name}").as_str()).unwrap());
+        let result = error.map_or(Ok(()), |error| Err(io::Error::other(error)));
+        ActionResult {
+            hook_error: hook_error.map(str::to_owned),
+            ..ActionResult::new(plugin, (result, String::new(), String::new()))
+        }
+    }
+
+    #[test]
+    fn test_update_pending() {
+        let mut pending = PendingHooks::from(["fixed".into(), "unreachable".into()]);
+
+        update_pending(
+            &mut pending,
+            &[
+                result("fixed", None, None),
+                result("unreachable", Some("git pull failed"), None),
+                result("broken", Some("`build` failed"), Some("`build` failed")),
+                result("unchanged", None, None),
+            ],
+        );
+
+        assert_eq!(
+            pending,
+            PendingHooks::from(["broken".into(), "unreachable".into()])
+        );
+    }
+}
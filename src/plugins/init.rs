@@ -6,7 +6,5 @@ use super::load;
 
 pub fn init() -> Result<()> {
     tmux::setup_keymaps()?;
-    load::load()?;
-
-    Ok(())
+    load::report("init", &load::load()?)
 }
@@ -1,4 +1,4 @@
-use std::collections::HashMap;
+use std::{collections::HashMap, fs, io, os::unix::fs::symlink, path::Path};
 
 use anyhow::{Context, Error, Result, anyhow};
 use cmd_lib::{FunChildren, spawn_with_output};
@@ -7,16 +7,43 @@ use futures::{StreamExt, stream::FuturesUnordered};
 use tokio::task;
 
 use crate::{
-    plugin::Plugin, prefix_lines::PrefixLines, progress_status::ProgressStatus,
-    tmux::ensure_plugins_dir_exists, truncate_ellipsis::TruncateEllipsis,
+    git,
+    lockfile::Lockfile,
+    operation_log::{self, Entry},
+    output,
+    plugin::Plugin,
+    prefix_lines::PrefixLines,
+    progress_status::ProgressStatus,
+    tmux::ensure_plugins_dir_exists,
+    truncate_ellipsis::TruncateEllipsis,
 };
 
-use super::action_result::ActionResult;
+use super::{
+    action_result::ActionResult,
+    hooks::{self, Hook},
+};
+
+pub async fn install() -> Result<Vec<ActionResult>> {
+    let mut lockfile = Lockfile::load()?;
+
+    let (installed, plugins): (Vec<_>, Vec<_>) = super::get_plugins()?
+        .into_iter()
+        .partition(|p| p.is_installed());
 
-pub async fn install() -> Result<()> {
-    let plugins = super::get_plugins()?
+    // Installed plugins that have drifted from the lockfile are reset to the locked commit
+    let (drifted, installed): (Vec<_>, Vec<_>) = installed.into_iter().partition(|plugin| {
+        lockfile.locked_commit(plugin).is_some_and(|locked_commit| {
+            git::head_commit(plugin.path()).is_ok_and(|head| head != locked_commit)
+        })
+    });
+
+    let plugins = plugins
         .into_iter()
-        .filter(|p| !p.is_installed())
+        .chain(drifted)
+        .map(|plugin| {
+            let locked_commit = lockfile.locked_commit(&plugin).map(str::to_owned);
+            (plugin, locked_commit)
+        })
         .collect::<Vec<_>>();
 
     let results = if super::do_parallel() {
@@ -25,35 +52,54 @@ pub async fn install() -> Result<()> {
         install_sequential(plugins)?
     };
 
-    for result in results {
-        if result.result.is_err() {
+    if !output::is_json() {
+        for result in results.iter().filter(|result| result.result.is_err()) {
             print_error(result);
         }
     }
-    Ok(())
+
+    // Lock newly installed and reset plugins as well as installed plugins that are not locked yet
+    let newly_installed = results
+        .iter()
+        .filter(|result| result.result.is_ok())
+        .map(|result| &result.plugin);
+    let unlocked = installed
+        .iter()
+        .filter(|plugin| lockfile.get(plugin.name()).is_none());
+    for plugin in newly_installed.chain(unlocked).collect::<Vec<_>>() {
+        lockfile.lock(plugin)?;
+    }
+    lockfile.save()?;
+
+    operation_log::record(
+        results
+            .iter()
+            .map(|result| Entry::from_action("install", result)),
+    );
+
+    Ok(results)
 }
 
-fn install_sequential(plugins: Vec<Plugin>) -> Result<Vec<ActionResult>> {
+fn install_sequential(plugins: Vec<(Plugin, Option<String>)>) -> Result<Vec<ActionResult>> {
     let mut results = vec![];
 
     let progress = ProgressStatus::new();
     let mut plugin_progresses = HashMap::new();
 
-    for plugin in &plugins {
-        let plugin_name = plugin.to_string().truncate_ellipsis(62);
-        let task_name = format!("Installing {plugin_name}");
+    for (plugin, _) in &plugins {
+        let task_name = task_name(plugin);
         let pt = progress.add_task(&task_name, "Waiting")?;
         plugin_progresses.insert(plugin.to_string(), pt);
     }
 
-    for plugin in plugins {
+    for (plugin, locked_commit) in plugins {
         let pt = plugin_progresses
             .get(&plugin.to_string())
             .expect("Plugin progress should be in map");
 
-        pt.set_status("Installing")?;
+        pt.set_status(action_name(&plugin))?;
 
-        let res = install_plugin(plugin)?;
+        let res = ActionResult::timed(|| install_plugin(plugin, locked_commit))?;
 
         if res.result.is_ok() {
             pt.set_success("Done")?;
@@ -66,20 +112,20 @@ fn install_sequential(plugins: Vec<Plugin>) -> Result<Vec<ActionResult>> {
     Ok(results)
 }
 
-async fn install_parallel(plugins: Vec<Plugin>) -> Result<Vec<ActionResult>> {
+async fn install_parallel(plugins: Vec<(Plugin, Option<String>)>) -> Result<Vec<ActionResult>> {
     let mut tasks: FuturesUnordered<task::JoinHandle<std::result::Result<ActionResult, Error>>> =
         FuturesUnordered::new();
     let progress = ProgressStatus::new();
 
-    for plugin in plugins {
-        let plugin_name = plugin.to_string().truncate_ellipsis(62);
-        let task_name = format!("Installing {plugin_name}");
+    for (plugin, locked_commit) in plugins {
+        let task_name = task_name(&plugin);
+        let status = action_name(&plugin);
         let pt = progress.add_task(&task_name, "Waiting")?;
 
         tasks.push(task::spawn(async move {
-            pt.set_status("Installing")?;
+            pt.set_status(status)?;
 
-            let res = install_plugin(plugin)?;
+            let res = ActionResult::timed(|| install_plugin(plugin, locked_commit))?;
 
             if res.result.is_ok() {
                 pt.set_success("Done")?;
@@ -101,23 +147,54 @@ async fn install_parallel(plugins: Vec<Plugin>) -> Result<Vec<ActionResult>> {
     Ok(results)
 }
 
-fn install_plugin(plugin: Plugin) -> Result<ActionResult> {
+/// Installed plugins are only reset to their locked commit
+fn action_name(plugin: &Plugin) -> &'static str {
+    if plugin.is_installed() {
+        "Resetting"
+    } else {
+        "Installing"
+    }
+}
+
+fn task_name(plugin: &Plugin) -> String {
+    let plugin_name = plugin.to_string().truncate_ellipsis(62);
+    format!("{} {plugin_name}", action_name(plugin))
+}
+
+fn install_plugin(plugin: Plugin, locked_commit: Option<String>) -> Result<ActionResult> {
     if plugin.is_installed() {
-        return Err(anyhow!("Plugin already installed: {plugin}"));
+        return match locked_commit {
+            Some(commit) => reset_to_locked_commit(plugin, &commit),
+            None => Err(anyhow!("Plugin already installed: {plugin}")),
+        };
     }
 
     ensure_plugins_dir_exists()?;
 
+    let result = if let Some(local_path) = plugin.local_path() {
+        link_plugin(plugin, &local_path)
+    } else {
+        clone_plugin(plugin, locked_commit)?
+    };
+
+    let result = hooks::run(result, Hook::Install)?;
+    Ok(remove_failed_install(result))
+}
+
+fn clone_plugin(plugin: Plugin, locked_commit: Option<String>) -> Result<ActionResult> {
     let url = plugin.url();
     let path = plugin.path();
 
-    let branch_args = match plugin.branch() {
-        Some(branch) => vec!["--branch", branch],
-        None => vec![],
+    // Plugins pinned to a `rev` are cloned with all branches, so that the commit is available
+    // even when it is not part of the default branch.
+    let clone_args = match (plugin.branch().or(plugin.tag()), plugin.rev()) {
+        (_, Some(_)) => vec![],
+        (Some(branch_or_tag), None) => vec!["--single-branch", "--branch", branch_or_tag],
+        (None, None) => vec!["--single-branch"],
     };
 
     let mut proc: FunChildren = spawn_with_output!(
-        GIT_TERMINAL_PROMPT=0 git clone --single-branch --recursive $[branch_args] $url $path;
+        GIT_TERMINAL_PROMPT=0 git clone --recursive $[clone_args] $url $path;
     )
     .context(format!(
         r#"Failed to spawn subprocess for installing "{}". Is `git` installed?"#,
@@ -125,18 +202,136 @@ fn install_plugin(plugin: Plugin) -> Result<ActionResult> {
     ))?;
     let out = proc.wait_with_all();
 
-    Ok(ActionResult::new(plugin, out))
+    let commit = locked_commit.or_else(|| plugin.rev().map(str::to_owned));
+
+    Ok(match commit {
+        Some(commit) if out.0.is_ok() => checkout_commit(plugin, &commit)?,
+        _ => ActionResult::new(plugin, out).with_commits(None),
+    })
 }
 
-fn print_error(result: ActionResult) {
+/// Reset an installed plugin to the commit recorded in the lockfile and run its update hooks.
+/// Plugins with local work are left alone, as it would be lost.
+fn reset_to_locked_commit(plugin: Plugin, commit: &str) -> Result<ActionResult> {
+    let path = plugin.path();
+    let old_commit = git::head_commit(path).ok();
+
+    let local_work = git::local_work(path)
+        .unwrap_or_else(|err| Some(format!("could not be checked for local work: {err:#}")));
+    if let Some(reason) = local_work {
+        let error = format!("Not resetting to locked commit {commit}, the plugin {reason}");
+        let output = (Err(io::Error::other(error.clone())), String::new(), error);
+        return Ok(ActionResult::new(plugin, output).with_commits(old_commit));
+    }
+
+    let result = checkout_commit(plugin, commit)?.with_commits(old_commit);
+    hooks::run(result, Hook::Update)
+}
+
+/// Remove what is left of a plugin whose install failed, e.g. because its `build` command failed
+/// after it has been cloned. Otherwise it would count as installed and never be installed again.
+/// Local plugins only lose their link.
+fn remove_failed_install(mut result: ActionResult) -> ActionResult {
+    let path = result.plugin.path().to_owned();
+    if result.result.is_ok() || path.symlink_metadata().is_err() {
+        return result;
+    }
+
+    let removed = if path.is_symlink() {
+        fs::remove_file(&path)
+    } else {
+        fs::remove_dir_all(&path)
+    };
+
+    let message = match removed {
+        Ok(()) => format!("Removed {}, so that it is installed again", path.display()),
+        Err(err) => format!("Failed to remove {}: {err}", path.display()),
+    };
+    if !result.stderr.is_empty() && !result.stderr.ends_with('\n') {
+        result.stderr.push('\n');
+    }
+    result.stderr += &message;
+    result.stderr.push('\n');
+
+    result
+}
+
+/// Install a local plugin by linking its directory into the plugins directory
+fn link_plugin(plugin: Plugin, local_path: &Path) -> ActionResult {
+    let path = plugin.path();
+
+    let result = if !local_path.is_dir() {
+        Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            format!(
+                "Local plugin directory does not exist: {}",
+                local_path.display()
+            ),
+        ))
+    } else {
+        // Replace a dangling link left behind by a local plugin that has been moved
+        if path.is_symlink() {
+            let _ = fs::remove_file(path);
+        }
+        symlink(local_path, path)
+    };
+
+    let (stdout, stderr) = match &result {
+        Ok(()) => (
+            format!("Linked {} -> {}", path.display(), local_path.display()),
+            String::new(),
+        ),
+        Err(err) => (String::new(), err.to_string()),
+    };
+
+    ActionResult::new(plugin, (result, stdout, stderr))
+}
+
+/// Reset a plugin to the commit recorded in the lockfile or the one it is pinned to. The commit is fetched explicitly if it is not part of the cloned branch.
+fn checkout_commit(plugin: Plugin, commit: &str) -> Result<ActionResult> {
+    if !commit.chars().all(|c| c.is_ascii_hexdigit()) {
+        return Err(anyhow!(
+            r#"Invalid commit "{commit}" for plugin "{plugin}""#
+        ));
+    }
+
+    let path = plugin.path();
+
+    let mut proc: FunChildren = spawn_with_output!(
+        bash -c "
+            set -e;
+            cd $path;
+            if ! git reset --quiet --hard $commit 2>/dev/null; then
+                GIT_TERMINAL_PROMPT=0 git fetch origin $commit;
+                git reset --hard $commit;
+            fi;
+            GIT_TERMINAL_PROMPT=0 git submodule update --init --recursive;
+        "
+    )
+    .context(format!(
+        r#"Failed to spawn subprocess for checking out commit of "{}". Is `git` installed?"#,
+        plugin
+    ))?;
+    let out = proc.wait_with_all();
+
+    Ok(ActionResult::new(plugin, out).with_commits(None))
+}
+
+fn print_error(result: &ActionResult) {
     eprintln!();
     eprintln!();
 
     eprintln!(
         "{}",
-        format!(r#"Failed to install plugin "{}""#, result.plugin)
-            .bold()
-            .red()
+        match result.old_commit {
+            Some(_) => format!(
+                r#"Failed to reset plugin "{}" to locked commit"#,
+                result.plugin
+            ),
+            None => format!(r#"Failed to install plugin "{}""#, result.plugin),
+        }
+        .bold()
+        .red()
     );
 
     eprint!(
@@ -155,7 +350,7 @@ fn print_error(result: ActionResult) {
             .stderr
             .prefix_lines(&"  err-out> ".bold().red().to_string())
     );
-    if !result.stdout.is_empty() && !result.stderr.ends_with("\n") {
+    if !result.stderr.is_empty() && !result.stderr.ends_with("\n") {
         eprintln!()
     }
 }
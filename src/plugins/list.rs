@@ -0,0 +1,130 @@
+use std::{collections::BTreeMap, path::PathBuf};
+
+use anyhow::Result;
+use colored::Colorize;
+use itertools::Itertools;
+use serde::Serialize;
+
+use crate::{git, plugin::Plugin};
+
+#[derive(Serialize)]
+pub struct PluginList {
+    pub plugins: Vec<PluginInfo>,
+    pub orphans: Vec<PathBuf>,
+}
+
+#[derive(Serialize)]
+pub struct PluginInfo {
+    pub name: String,
+    pub url: String,
+    pub branch: Option<String>,
+    pub attributes: BTreeMap<String, String>,
+    pub path: PathBuf,
+    pub installed: bool,
+    pub commit: Option<String>,
+    /// Why the plugin is skipped on this machine, if it is
+    pub skip_reason: Option<String>,
+    /// Why the plugin is not loaded although it is installed, if it is disabled
+    pub disable_reason: Option<String>,
+}
+
+impl From<&Plugin> for PluginInfo {
+    fn from(plugin: &Plugin) -> Self {
+        let path = plugin.path();
+        let installed = plugin.is_installed();
+
+        let (branch, commit) = if installed {
+            (
+                plugin
+                    .branch()
+                    .map(str::to_owned)
+                    .or_else(|| git::current_branch(path)),
+                git::head_commit(path).ok(),
+            )
+        } else {
+            (plugin.branch().map(str::to_owned), None)
+        };
+
+        PluginInfo {
+            name: plugin.name().to_owned(),
+            url: plugin.repo_url().to_string(),
+            branch,
+            attributes: plugin
+                .attributes()
+                .iter()
+                .map(|(key, val)| (key.to_string(), val.to_owned()))
+                .collect(),
+            path: path.to_owned(),
+            installed,
+            commit,
+            skip_reason: plugin.skip_reason().map(str::to_owned),
+            disable_reason: plugin.disable_reason().map(str::to_owned),
+        }
+    }
+}
+
+pub fn list() -> Result<PluginList> {
+    let plugins = super::get_all_plugins()?;
+    let orphans = super::clean::get_orphans(&plugins)?;
+
+    Ok(PluginList {
+        plugins: plugins.iter().map(PluginInfo::from).collect(),
+        orphans,
+    })
+}
+
+pub fn print_list(list: &PluginList) {
+    println!("{}", "==> Plugins".bold());
+    for plugin in &list.plugins {
+        print_plugin(plugin);
+    }
+    if list.plugins.is_empty() {
+        println!("    (none)");
+    }
+
+    println!();
+    println!("{}", "==> Orphans (removed by `clean`)".bold());
+    for orphan in &list.orphans {
+        println!("    {}", orphan.display());
+    }
+    if list.orphans.is_empty() {
+        println!("    (none)");
+    }
+}
+
+fn print_plugin(plugin: &PluginInfo) {
+    let status = if plugin.skip_reason.is_some() {
+        "skipped".bold().yellow()
+    } else if plugin.disable_reason.is_some() {
+        "disabled".bold().yellow()
+    } else if plugin.installed {
+        "installed".bold().green()
+    } else {
+        "not installed".bold().red()
+    };
+    println!("{} ({status})", plugin.name.bold());
+
+    let attributes = plugin
+        .attributes
+        .iter()
+        .map(|(key, val)| format!("{key}={val}"))
+        .join(", ");
+
+    print_field("url", &plugin.url);
+    print_field("branch", plugin.branch.as_deref().unwrap_or("-"));
+    print_field("attributes", &attributes);
+    print_field("path", &plugin.path.display().to_string());
+    print_field("commit", plugin.commit.as_deref().unwrap_or("-"));
+    if let Some(reason) = &plugin.skip_reason {
+        print_field("skipped", reason);
+    }
+    if let Some(reason) = &plugin.disable_reason {
+        print_field("disabled", reason);
+    }
+}
+
+fn print_field(name: &str, value: &str) {
+    let value = if value.is_empty() { "-" } else { value };
+    let name = format!("{:<11}", format!("{name}:"));
+    println!("    {} {value}", name.dimmed());
+}
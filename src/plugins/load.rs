@@ -1,24 +1,201 @@
-use anyhow::{Context, Result};
+use std::{
+    cmp::Reverse,
+    env,
+    fs::{self, File, OpenOptions},
+    io,
+    os::unix::{fs::FileExt, process::CommandExt},
+    path::{Path, PathBuf},
+    process::{self, Child, Command, Stdio},
+    sync::atomic::{AtomicUsize, Ordering},
+    thread,
+    time::{Duration, Instant},
+};
+
+use anyhow::{Context, Result, anyhow, bail};
 use cmd_lib::run_cmd;
+use colored::Colorize;
 use glob::glob;
 use is_executable::IsExecutable;
-use log::info;
+use itertools::Itertools;
+use log::{info, warn};
 use rayon::prelude::*;
+use serde::{Serialize, Serializer, ser::SerializeStruct};
+
+use crate::{
+    operation_log::{self, Entry},
+    output,
+    plugin::Plugin,
+    prefix_lines::PrefixLines,
+    report,
+    tmux_changes::ChangeTracker,
+    utils::as_millis,
+};
+
+/// The outcome of loading a plugin and how long it took
+pub struct LoadResult {
+    pub plugin: String,
+    pub error: Option<String>,
+    pub stdout: String,
+    pub stderr: String,
+    pub duration: Duration,
+    pub files: Vec<FileTiming>,
+}
+
+impl LoadResult {
+    fn new(plugin: &Plugin) -> LoadResult {
+        LoadResult {
+            plugin: plugin.name().to_owned(),
+            error: None,
+            stdout: String::new(),
+            stderr: String::new(),
+            duration: Duration::ZERO,
+            files: vec![],
+        }
+    }
+
+    /// The result of a plugin that has not been loaded because of an error
+    fn failed(plugin: &Plugin, error: String) -> LoadResult {
+        LoadResult {
+            error: Some(error),
+            ..LoadResult::new(plugin)
+        }
+    }
+
+    pub fn is_failed(&self) -> bool {
+        self.error.is_some()
+    }
+}
+
+impl Serialize for LoadResult {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let status = match self.error {
+            None => "success",
+            Some(_) => "failed",
+        };
+
+        let mut state = serializer.serialize_struct("LoadResult", 7)?;
+        state.serialize_field("plugin", &self.plugin)?;
+        state.serialize_field("status", status)?;
+        state.serialize_field("error", &self.error)?;
+        state.serialize_field("stdout", &self.stdout)?;
+        state.serialize_field("stderr", &self.stderr)?;
+        state.serialize_field("duration_ms", &as_millis(self.duration))?;
+        state.serialize_field("files", &self.files)?;
+        state.end()
+    }
+}
+
+/// How long running a single init file of a plugin took
+#[derive(Serialize)]
+pub struct FileTiming {
+    pub path: PathBuf,
+    #[serde(rename = "duration_ms", serialize_with = "serialize_millis")]
+    pub duration: Duration,
+}
 
-use crate::plugin::Plugin;
+fn serialize_millis<S: Serializer>(duration: &Duration, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_f64(as_millis(*duration))
+}
 
-pub fn load() -> Result<()> {
+/// Load all plugins, even when some of them fail to load. Returns the result of each plugin in
+/// load order. The changes plugins make to tmux are tracked, so that they can be unloaded later.
+pub fn load() -> Result<Vec<LoadResult>> {
     let plugins = super::get_plugins()?;
-    let (parallel, non_parallel) = plugins.into_iter().partition::<Vec<_>, _>(|p| p.parallel());
+    let mut tracker = ChangeTracker::start();
+
+    let results = load_plugins(plugins, &mut tracker)?;
 
-    non_parallel.iter().try_for_each(load_plugin)?;
-    parallel.par_iter().try_for_each(load_plugin)
+    if let Some(tracker) = tracker {
+        tracker.finish();
+    }
+    Ok(results)
 }
 
-fn load_plugin(plugin: &Plugin) -> Result<()> {
+/// Load the given plugins in load order. Plugins whose dependencies are not configured, skipped or
+/// failed to load are not loaded and reported as failed.
+fn load_plugins(
+    plugins: Vec<Plugin>,
+    tracker: &mut Option<ChangeTracker>,
+) -> Result<Vec<LoadResult>> {
+    let mut results = vec![];
+    for (plugin, name) in super::load_order::missing_dependencies(&plugins) {
+        results.push(LoadResult::failed(
+            plugin,
+            format!(r#"Dependency "{name}" is not configured or skipped on this machine"#),
+        ));
+    }
+
+    // Disabled plugins are part of the load order, so that they keep the other plugins in order
+    for wave in super::load_order::waves(plugins)? {
+        let (parallel, non_parallel) = wave
+            .into_iter()
+            .filter(is_enabled)
+            .filter(|plugin| {
+                // Plugins with missing dependencies have been reported already
+                if results.iter().any(|result| result.plugin == plugin.name()) {
+                    return false;
+                }
+                let Some(name) = failed_dependency(plugin, &results) else {
+                    return true;
+                };
+                let error = format!(r#"Dependency "{name}" failed to load"#);
+                results.push(LoadResult::failed(plugin, error));
+                false
+            })
+            .partition::<Vec<_>, _>(|p| p.parallel());
+
+        for plugin in &non_parallel {
+            results.push(load_plugin(plugin));
+            if let Some(tracker) = tracker {
+                tracker.track(&[plugin]);
+            }
+        }
+        results.extend(parallel.par_iter().map(load_plugin).collect::<Vec<_>>());
+        if let Some(tracker) = tracker {
+            tracker.track(&parallel.iter().collect_vec());
+        }
+    }
+
+    Ok(results)
+}
+
+/// The name of a dependency of the plugin that failed to load. Dependencies are always loaded in
+/// an earlier wave, so their results are known already.
+fn failed_dependency<'a>(plugin: &'a Plugin, results: &[LoadResult]) -> Option<&'a str> {
+    super::load_order::dependencies(plugin).find(|name| {
+        results
+            .iter()
+            .any(|result| result.plugin == *name && result.is_failed())
+    })
+}
+
+fn is_enabled(plugin: &Plugin) -> bool {
+    if let Some(reason) = plugin.disable_reason() {
+        info!("Not loading plugin {}: {reason}", plugin.name());
+        return false;
+    }
+    true
+}
+
+fn load_plugin(plugin: &Plugin) -> LoadResult {
     info!("Loading plugin {}", plugin.name());
+    let start = Instant::now();
 
-    let path_str = plugin.path().to_str().context("Path is not valid UTF-8")?;
+    let mut result = LoadResult::new(plugin);
+    if let Err(err) = run_init_files(plugin, &mut result) {
+        result.error = Some(format!("{err:#}"));
+    }
+
+    result.duration = start.elapsed();
+    result
+}
+
+fn run_init_files(plugin: &Plugin, result: &mut LoadResult) -> Result<()> {
+    // Local plugins are loaded from their directory, even when they have not been linked yet
+    let path = plugin
+        .local_path()
+        .unwrap_or_else(|| plugin.path().to_owned());
+    let path_str = path.to_str().context("Path is not valid UTF-8")?;
 
     // Find all plugin init files (executable files ending in `.tmux`)
     for entry in glob(&format!("{path_str}/*.tmux"))? {
@@ -27,12 +204,384 @@ fn load_plugin(plugin: &Plugin) -> Result<()> {
             continue;
         }
 
-        let init_file = entry.to_str().context("Path is not valid UTF-8")?;
+        let start = Instant::now();
+        let (status, stdout, stderr) = run_init_file(&entry, plugin.timeout())?;
+        result.stdout.push_str(&stdout);
+        result.stderr.push_str(&stderr);
+        result.files.push(FileTiming {
+            path: entry,
+            duration: start.elapsed(),
+        });
 
-        // Run the init plugin file
-        run_cmd!($init_file)
-            .or_else(|_| run_cmd!(/bin/sh $init_file))
-            .context(format!(r#"Failed to load plugin "{}""#, plugin.name()))?;
+        // Don't run the remaining init files of a plugin that is broken
+        status?;
     }
     Ok(())
 }
+
+/// Run a plugin init file and kill it, including all processes it started, if it exceeds the
+/// timeout. Returns whether it succeeded along with its stdout and stderr.
+fn run_init_file(
+    init_file: &Path,
+    timeout: Option<Duration>,
+) -> Result<(Result<()>, String, String)> {
+    let stdout = CapturedOutput::new().context("Failed to create file for stdout")?;
+    let stderr = CapturedOutput::new().context("Failed to create file for stderr")?;
+
+    let mut child = spawn(&mut Command::new(init_file), &stdout, &stderr)
+        // Init files without a shebang can't be executed directly
+        .or_else(|_| spawn(Command::new("/bin/sh").arg(init_file), &stdout, &stderr))
+        .context(format!("Failed to run {}", init_file.display()))?;
+
+    let status = wait(&mut child, init_file, timeout);
+
+    Ok((status, stdout.read(), stderr.read()))
+}
+
+fn wait(child: &mut Child, init_file: &Path, timeout: Option<Duration>) -> Result<()> {
+    let Some(timeout) = timeout else {
+        let status = child.wait()?;
+        if !status.success() {
+            bail!("{} failed with {status}", init_file.display());
+        }
+        return Ok(());
+    };
+
+    let start = Instant::now();
+    // Poll quickly at first, so that the many init files that finish right away don't get delayed
+    let mut interval = Duration::from_millis(1);
+    loop {
+        if let Some(status) = child.try_wait()? {
+            if !status.success() {
+                bail!("{} failed with {status}", init_file.display());
+            }
+            return Ok(());
+        }
+
+        if start.elapsed() >= timeout {
+            let pgid = child.id();
+            // The process group may already be gone, which is fine
+            let _ = run_cmd!(kill -KILL -- -$pgid 2>/dev/null);
+            let _ = child.wait();
+            bail!(
+                "{} did not finish within {:.1}s and has been killed",
+                init_file.display(),
+                timeout.as_secs_f64()
+            );
+        }
+
+        thread::sleep(interval);
+        interval = (interval * 2).min(Duration::from_millis(50));
+    }
+}
+
+/// Spawn an init file in its own process group and capture its output
+fn spawn(
+    command: &mut Command,
+    stdout: &CapturedOutput,
+    stderr: &CapturedOutput,
+) -> io::Result<Child> {
+    command
+        .stdin(Stdio::null())
+        .stdout(stdout.stdio()?)
+        .stderr(stderr.stdio()?)
+        .process_group(0)
+        .spawn()
+}
+
+/// At most this much of the output of an init file is kept, the rest is cut off at the start
+const MAX_OUTPUT_LEN: u64 = 1024 * 1024;
+
+/// Output of an init file. It is written to an unlinked temporary file rather than a pipe:
+/// Background processes started by the init file inherit the file, so with a pipe, reading the
+/// output would have to wait for them and they would get `SIGPIPE` once ahiru-tpm has exited.
+///
+/// The file is emptied once it has been read and is opened in append mode, so that only what
+/// background processes write afterwards takes up space. That space is freed when the last of
+/// them exits.
+struct CapturedOutput {
+    file: File,
+}
+
+impl CapturedOutput {
+    fn new() -> io::Result<CapturedOutput> {
+        static COUNT: AtomicUsize = AtomicUsize::new(0);
+
+        let path = env::temp_dir().join(format!(
+            "ahiru-tpm-{}-{}.out",
+            process::id(),
+            COUNT.fetch_add(1, Ordering::Relaxed)
+        ));
+        let file = OpenOptions::new()
+            .read(true)
+            .append(true)
+            .create_new(true)
+            .open(&path)?;
+        fs::remove_file(&path)?;
+
+        Ok(CapturedOutput { file })
+    }
+
+    fn stdio(&self) -> io::Result<Stdio> {
+        Ok(self.file.try_clone()?.into())
+    }
+
+    /// Get the output written so far, at most `MAX_OUTPUT_LEN` bytes of it, and empty the file.
+    /// Anything background processes write later is ignored.
+    fn read(&self) -> String {
+        let len = self.file.metadata().map_or(0, |meta| meta.len());
+        let start = len.saturating_sub(MAX_OUTPUT_LEN);
+        let mut buffer = vec![0; (len - start) as usize];
+        // Read without moving the offset, which is shared with processes that are still writing
+        let output = match self.file.read_exact_at(&mut buffer, start) {
+            Ok(()) if start > 0 => format!(
+                "[{start} bytes of output cut off]\n{}",
+                String::from_utf8_lossy(&buffer)
+            ),
+            Ok(()) => String::from_utf8_lossy(&buffer).into_owned(),
+            Err(_) => String::new(),
+        };
+
+        if let Err(err) = self.file.set_len(0) {
+            warn!("Failed to empty file with output of init file: {err}");
+        }
+        output
+    }
+}
+
+/// Report the results of loading plugins: The full log is stored for `ahiru-tpm log --last-run`, and
+/// plugins that failed to load are printed and shown in tmux. Returns an error if any plugin
+/// failed.
+pub fn report(command: &str, results: &[LoadResult]) -> Result<()> {
+    if let Err(err) = report::save_last_run(command, &format_log(results)) {
+        warn!("Failed to save log: {err:#}");
+    }
+    operation_log::record(
+        results
+            .iter()
+            .map(|result| Entry::from_load(command, result)),
+    );
+
+    let failed = results
+        .iter()
+        .filter(|result| result.is_failed())
+        .collect_vec();
+    if failed.is_empty() {
+        return Ok(());
+    }
+
+    if !output::is_json() {
+        failed.iter().for_each(|result| print_error(result));
+        eprintln!();
+    }
+
+    let message = format!(
+        "Failed to load {} plugin(s): {}",
+        failed.len(),
+        failed.iter().map(|result| &result.plugin).join(", ")
+    );
+    report::report_in_tmux(&message);
+
+    Err(anyhow!(message))
+}
+
+/// Format the results of all plugins as plain text, including the output of failed plugins
+fn format_log(results: &[LoadResult]) -> String {
+    let mut log = String::new();
+    for result in results {
+        let status = if result.is_failed() { "failed" } else { "ok" };
+        log += &format!(
+            "{status:<6} {} ({})\n",
+            result.plugin,
+            format_duration(result.duration)
+        );
+
+        if let Some(error) = &result.error {
+            log += &error.prefix_lines("  ");
+            log += "\n";
+            for (output, prefix) in [(&result.stdout, "  out> "), (&result.stderr, "  err-out> ")] {
+                if !output.is_empty() {
+                    log += &output.prefix_lines(prefix);
+                    log += "\n";
+                }
+            }
+        }
+    }
+    log
+}
+
+fn print_error(result: &LoadResult) {
+    eprintln!();
+    eprintln!(
+        "{}",
+        format!(r#"Failed to load plugin "{}""#, result.plugin)
+            .bold()
+            .red()
+    );
+    if let Some(error) = &result.error {
+        eprintln!("{}", error.prefix_lines("  "));
+    }
+
+    if !result.stdout.is_empty() {
+        eprintln!(
+            "{}",
+            result
+                .stdout
+                .prefix_lines(&"  out> ".bold().green().to_string())
+        );
+    }
+    if !result.stderr.is_empty() {
+        eprintln!(
+            "{}",
+            result
+                .stderr
+                .prefix_lines(&"  err-out> ".bold().red().to_string())
+        );
+    }
+}
+
+/// Print the load times of all plugins, slowest first. `total` is the wall time of loading all
+/// plugins, which is shorter than the sum of the load times when plugins are loaded in parallel.
+pub fn print_profile(results: &[LoadResult], total: Duration) {
+    let mut results = results.iter().collect::<Vec<_>>();
+    results.sort_by_key(|result| Reverse(result.duration));
+
+    println!("{}", "==> Plugin load times".bold());
+
+    for result in results {
+        let name = match result.is_failed() {
+            true => format!("{} (failed)", result.plugin).bold().red(),
+            false => result.plugin.bold(),
+        };
+        println!("{:>9} {name}", format_duration(result.duration));
+        for file in &result.files {
+            let name = file.path.file_name().unwrap_or_default().to_string_lossy();
+            println!(
+                "{:>11} {}",
+                format_duration(file.duration).dimmed(),
+                name.dimmed()
+            );
+        }
+    }
+
+    println!();
+    println!(
+        "{}",
+        format!("==> Total: {}", format_duration(total)).bold()
+    );
+}
+
+fn format_duration(duration: Duration) -> String {
+    format!("{:.1}ms", as_millis(duration))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, io::Write, os::unix::fs::PermissionsExt};
+
+    use super::*;
+    use crate::{spec::Spec, test_utils::setup_dir};
+
+    #[test]
+    fn test_skip_plugins_with_failed_dependencies() {
+        let dir = setup_dir(
+            "load-dependencies",
+            &[
+                ("working/init.tmux", "#!/bin/sh\necho loaded"),
+                ("broken/init.tmux", "#!/bin/sh\nexit 3"),
+                ("dependent/init.tmux", "#!/bin/sh"),
+                ("indirect/init.tmux", "#!/bin/sh"),
+                ("orphaned/init.tmux", "#!/bin/sh"),
+            ],
+        );
+        for name in ["working", "broken", "dependent", "indirect", "orphaned"] {
+            let init_file = dir.join(name).join("init.tmux");
+            fs::set_permissions(init_file, fs::Permissions::from_mode(0o755)).unwrap();
+        }
+
+        let dir = dir.display();
+        let plugins = [
+            format!("path:{dir}/indirect; depends = dependent"),
+            format!("path:{dir}/dependent; depends = 'working broken'"),
+            format!("path:{dir}/working"),
+            format!("path:{dir}/broken"),
+            format!("path:{dir}/orphaned; depends = skipped"),
+        ]
+        .iter()
+        .map(|spec| Plugin::from(Spec::try_from(spec.as_str()).unwrap()))
+        .collect();
+
+        let results = load_plugins(plugins, &mut None).unwrap();
+        let outcomes = results
+            .iter()
+            .map(|result| (result.plugin.as_str(), result.error.as_deref()))
+            .collect_vec();
+
+        let broken_error = format!("{dir}/broken/init.tmux failed with exit status: 3");
+        assert_eq!(
+            outcomes,
+            vec![
+                (
+                    "orphaned",
+                    Some(r#"Dependency "skipped" is not configured or skipped on this machine"#)
+                ),
+                ("working", None),
+                ("broken", Some(broken_error.as_str())),
+                ("dependent", Some(r#"Dependency "broken" failed to load"#)),
+                ("indirect", Some(r#"Dependency "dependent" failed to load"#)),
+            ]
+        );
+        assert_eq!(results[1].stdout, "loaded\n");
+    }
+
+    #[test]
+    fn test_skip_disabled_plugins() {
+        let dir = setup_dir(
+            "load-disabled",
+            &[
+                ("enabled/init.tmux", "#!/bin/sh"),
+                ("disabled/init.tmux", "#!/bin/sh\nexit 1"),
+            ],
+        );
+        for name in ["enabled", "disabled"] {
+            let init_file = dir.join(name).join("init.tmux");
+            fs::set_permissions(init_file, fs::Permissions::from_mode(0o755)).unwrap();
+        }
+
+        let dir = dir.display();
+        let plugins = [
+            format!("path:{dir}/disabled; enabled = false"),
+            format!("path:{dir}/enabled; after = disabled"),
+        ]
+        .iter()
+        .map(|spec| Plugin::from(Spec::try_from(spec.as_str()).unwrap()))
+        .collect();
+
+        let results = load_plugins(plugins, &mut None).unwrap();
+        let outcomes = results
+            .iter()
+            .map(|result| (result.plugin.as_str(), result.is_failed()))
+            .collect_vec();
+        assert_eq!(outcomes, vec![("enabled", false)]);
+    }
+
+    #[test]
+    fn test_captured_output() {
+        let output = CapturedOutput::new().unwrap();
+        let mut file = output.file.try_clone().unwrap();
+
+        file.write_all(b"hello\n").unwrap();
+        assert_eq!(output.read(), "hello\n");
+        // The file is emptied, so that only later output takes up space
+        assert_eq!(output.read(), "");
+
+        file.write_all(&vec![b'x'; MAX_OUTPUT_LEN as usize + 3])
+            .unwrap();
+        let read = output.read();
+        assert!(read.starts_with("[3 bytes of output cut off]\n"));
+        assert_eq!(
+            read.len() - read.find('\n').unwrap() - 1,
+            MAX_OUTPUT_LEN as usize
+        );
+    }
+}
@@ -0,0 +1,213 @@
+use std::{
+    borrow::Borrow,
+    collections::{BTreeSet, HashMap},
+};
+
+use anyhow::{Result, anyhow};
+use itertools::Itertools;
+
+use crate::{attribute::Attribute, plugin::Plugin};
+
+/// Group plugins into waves that are loaded one after another, so that every plugin is loaded
+/// after the plugins it should be loaded after (`after`/`depends`) and before the ones it should
+/// be loaded before (`before`). Plugins within a wave don't depend on each other and keep the
+/// order of the config.
+///
+/// Plugins named in `after`, `before` and `depends` that are not configured are ignored here.
+/// Plugins whose dependencies are missing are not loaded, see [`missing_dependencies`].
+pub fn waves(plugins: Vec<Plugin>) -> Result<Vec<Vec<Plugin>>> {
+    let waves = wave_indices(&plugins)?;
+
+    let mut plugins: Vec<Option<Plugin>> = plugins.into_iter().map(Some).collect();
+    Ok(waves
+        .into_iter()
+        .map(|wave| {
+            wave.into_iter()
+                .map(|idx| {
+                    plugins[idx]
+                        .take()
+                        .expect("Plugin should be in one wave only")
+                })
+                .collect()
+        })
+        .collect())
+}
+
+/// Check that the load order of the plugins can be resolved, i.e. that there are no cycles and all
+/// dependencies are configured
+pub fn validate(plugins: &[&Plugin]) -> Result<()> {
+    wave_indices(plugins)?;
+
+    match missing_dependencies(plugins).first() {
+        Some((plugin, name)) => Err(anyhow!(
+            r#"Plugin "{}" depends on plugin "{name}", which is not configured or skipped on this machine"#,
+            plugin.name()
+        )),
+        None => Ok(()),
+    }
+}
+
+/// The plugins named in the `depends` attribute of a plugin
+pub fn dependencies(plugin: &Plugin) -> impl Iterator<Item = &str> {
+    names(plugin, Attribute::Depends)
+}
+
+/// Find the plugins that depend on a plugin that is not among the given ones, e.g. because it is
+/// skipped on this machine. Returns each such plugin along with the name of the missing plugin.
+pub fn missing_dependencies<P: Borrow<Plugin>>(plugins: &[P]) -> Vec<(&Plugin, &str)> {
+    let plugins = plugins.iter().map(Borrow::borrow).collect_vec();
+    plugins
+        .iter()
+        .flat_map(|&plugin| {
+            dependencies(plugin)
+                .filter(|name| !plugins.iter().any(|other| other.name() == *name))
+                .map(move |name| (plugin, name))
+        })
+        .collect()
+}
+
+fn wave_indices<P: Borrow<Plugin>>(plugins: &[P]) -> Result<Vec<Vec<usize>>> {
+    let plugins = plugins.iter().map(Borrow::borrow).collect_vec();
+    let index: HashMap<&str, usize> = plugins
+        .iter()
+        .enumerate()
+        .map(|(idx, plugin)| (plugin.name(), idx))
+        .collect();
+
+    // The plugins that have to be loaded before each plugin
+    let mut dependencies: Vec<BTreeSet<usize>> = vec![BTreeSet::new(); plugins.len()];
+
+    for (idx, plugin) in plugins.iter().enumerate() {
+        for name in names(plugin, Attribute::After) {
+            if let Some(&other) = index.get(name) {
+                dependencies[idx].insert(other);
+            }
+        }
+
+        for name in names(plugin, Attribute::Depends) {
+            if let Some(&other) = index.get(name) {
+                dependencies[idx].insert(other);
+            }
+        }
+
+        for name in names(plugin, Attribute::Before) {
+            if let Some(&other) = index.get(name) {
+                dependencies[other].insert(idx);
+            }
+        }
+    }
+
+    let mut loaded = vec![false; plugins.len()];
+    let mut waves = vec![];
+
+    while loaded.iter().any(|loaded| !loaded) {
+        let wave = (0..plugins.len())
+            .filter(|&idx| !loaded[idx])
+            .filter(|&idx| dependencies[idx].iter().all(|&other| loaded[other]))
+            .collect_vec();
+
+        if wave.is_empty() {
+            let cycle = (0..plugins.len())
+                .filter(|&idx| !loaded[idx])
+                .map(|idx| plugins[idx].name())
+                .join(", ");
+            return Err(anyhow!("Load order of plugins contains a cycle: {cycle}"));
+        }
+
+        for &idx in &wave {
+            loaded[idx] = true;
+        }
+        waves.push(wave);
+    }
+
+    Ok(waves)
+}
+
+/// The plugin names listed in an ordering attribute, separated by whitespace
+fn names(plugin: &Plugin, attribute: Attribute) -> impl Iterator<Item = &str> {
+    plugin
+        .attributes()
+        .get(&attribute)
+        .map(|names| names.split_whitespace())
+        .into_iter()
+        .flatten()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::spec::Spec;
+
+    use super::*;
+
+    fn plugins(specs: &[&str]) -> Vec<Plugin> {
+        specs
+            .iter()
+            .map(|spec| Plugin::from(Spec::try_from(*spec).unwrap()))
+            .collect()
+    }
+
+    fn wave_names(plugins: Vec<Plugin>) -> Vec<Vec<String>> {
+        waves(plugins)
+            .unwrap()
+            .iter()
+            .map(|wave| wave.iter().map(|plugin| plugin.name().to_owned()).collect())
+            .collect()
+    }
+
+    #[test]
+    fn test_waves() {
+        let plugins = plugins(&[
+            "user/cpu; after = catppuccin",
+            "user/battery; depends = catppuccin",
+            "user/sensible",
+            "user/catppuccin",
+            "user/resurrect; before = 'continuum cpu', after = unknown",
+            "user/continuum",
+        ]);
+
+        assert_eq!(
+            wave_names(plugins),
+            vec![
+                vec!["sensible", "catppuccin", "resurrect"],
+                vec!["cpu", "battery", "continuum"],
+            ]
+        );
+    }
+
+    #[test]
+    fn test_waves_without_ordering() {
+        let plugins = plugins(&["user/a", "user/b"]);
+        assert_eq!(wave_names(plugins), vec![vec!["a", "b"]]);
+    }
+
+    #[test]
+    fn test_waves_errors() {
+        let cyclic = plugins(&[
+            "user/a; after = b, before = c",
+            "user/b; after = c",
+            "user/c",
+        ]);
+        let err = wave_indices(&cyclic).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Load order of plugins contains a cycle: a, b, c"
+        );
+
+        let missing = plugins(&["user/a; depends = b"]);
+        assert!(validate(&missing.iter().collect_vec()).is_err());
+    }
+
+    #[test]
+    fn test_missing_dependencies() {
+        let plugins = plugins(&["user/a; depends = 'b c'", "user/b", "user/d; after = e"]);
+
+        let missing = missing_dependencies(&plugins)
+            .into_iter()
+            .map(|(plugin, name)| (plugin.name(), name))
+            .collect_vec();
+        assert_eq!(missing, vec![("a", "c")]);
+
+        // Plugins with missing dependencies are still ordered, but not loaded
+        assert_eq!(wave_names(plugins), vec![vec!["b", "d"], vec!["a"]]);
+    }
+}
@@ -1,12 +1,24 @@
-mod action_result;
+pub mod action_result;
+pub mod check;
 pub mod clean;
+pub mod disable;
+pub mod history;
+pub mod hooks;
 pub mod init;
 pub mod install;
+pub mod list;
 pub mod load;
+pub mod load_order;
+pub mod rollback;
 pub mod sync;
+pub mod unload;
 pub mod update;
 
-use std::path::Path;
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    time::Duration,
+};
 
 use anyhow::{Context, Result, anyhow};
 use cached::proc_macro::cached;
@@ -14,6 +26,7 @@ use itertools::Itertools;
 use log::warn;
 
 use crate::{
+    diagnostic::{Diagnostic, Location},
     plugin::Plugin,
     spec::Spec,
     tmux::{self},
@@ -22,64 +35,159 @@ use crate::{
 };
 
 pub fn load_specs() -> Result<Vec<Spec>> {
-    let configs = tmux::get_existing_config_paths();
+    let configs = tmux::get_config_paths();
 
     if configs.is_empty() {
         return Err(anyhow!("Failed to find any tmux config files"));
     };
 
+    let mut warnings = vec![];
     let specs: Vec<Spec> = configs
         .into_iter()
         .map(|p| {
-            get_specs_from_config(&p).context(format!(
+            get_specs_from_config(&p, &mut warnings).context(format!(
                 "Failed to load specs from config file: {}",
                 p.display()
             ))
         })
         .flatten_ok()
+        .map_ok(|(spec, _)| spec)
         .collect::<Result<Vec<_>>>()?;
 
+    warnings.iter().for_each(Diagnostic::report);
+
     Ok(specs)
 }
 
-fn get_specs_from_config(path: &Path) -> Result<Vec<Spec>> {
-    let it = tmux_config_parser::parse(path)
-        .context(format!(
-            "Failed to parse specs from config file: {}",
-            path.display()
-        ))?
-        .into_iter()
-        .map(|directive| match directive {
-            ConfigDirective::PluginSpec(spec) => Ok(vec![spec]),
-            ConfigDirective::Source(path) => get_specs_from_config(path.as_ref()),
-        })
-        .flatten_ok()
-        .collect::<Result<Vec<_>>>()?;
+/// Collect the specs of a config file and all files sourced by it, along with the location they
+/// are defined at. Problems that don't prevent loading the specs are added to `warnings`.
+pub fn get_specs_from_config(
+    path: &Path,
+    warnings: &mut Vec<Diagnostic>,
+) -> Result<Vec<(Spec, Location)>> {
+    get_specs_from_sourced_config(path, &mut vec![], warnings)
+}
+
+/// Collect the specs of a config file and all files sourced by it. `sourcing` holds the files
+/// that are currently being sourced, so that a config that (indirectly) sources itself is only
+/// read once.
+fn get_specs_from_sourced_config(
+    path: &Path,
+    sourcing: &mut Vec<PathBuf>,
+    warnings: &mut Vec<Diagnostic>,
+) -> Result<Vec<(Spec, Location)>> {
+    let canonical_path = path.canonicalize().unwrap_or_else(|_| path.to_owned());
+    if sourcing.contains(&canonical_path) {
+        warn!("Config file sources itself, skipping: {}", path.display());
+        return Ok(vec![]);
+    }
+    sourcing.push(canonical_path);
+
+    let directives = tmux_config_parser::parse_with_warnings(path, warnings).context(format!(
+        "Failed to parse specs from config file: {}",
+        path.display()
+    ))?;
+
+    let mut specs = vec![];
+    for directive in directives {
+        match directive {
+            ConfigDirective::PluginSpec { spec, location } => specs.push((spec, location)),
+            ConfigDirective::Source {
+                path,
+                quiet,
+                location,
+            } => {
+                if !path.exists() {
+                    if quiet {
+                        continue;
+                    }
+                    return Err(Diagnostic::error(
+                        location,
+                        format!("Sourced file does not exist: {}", path.display()),
+                    )
+                    .with_hint(tmux_config_parser::SOURCE_QUIET_HINT)
+                    .into());
+                }
+                specs.extend(get_specs_from_sourced_config(&path, sourcing, warnings)?);
+            }
+        }
+    }
+
+    sourcing.pop();
 
-    Ok(it)
+    Ok(specs)
 }
 
-pub fn get_plugins() -> Result<Vec<Plugin>> {
-    let legacy_plugins: Vec<Plugin> = tmux::get_option("@tpm_plugins")
+/// Load the specs of plugins defined using the legacy `@tpm_plugins` option
+pub fn load_legacy_specs() -> Result<Vec<Spec>> {
+    tmux::get_option("@tpm_plugins")
         .unwrap_or("".to_owned())
         .split(' ')
         .filter(|&spec| !spec.is_empty())
         .map(Spec::try_from_legacy)
-        .map_ok(Plugin::from)
-        .collect::<Result<_>>()?;
+        .collect()
+}
+
+/// Get all plugins defined in the tmux config, including the ones that are skipped on this
+/// machine because their activation conditions don't hold
+pub fn get_all_plugins() -> Result<Vec<Plugin>> {
+    let legacy_plugins: Vec<Plugin> = load_legacy_specs()?.into_iter().map(Plugin::from).collect();
 
     let plugins: Vec<Plugin> = load_specs()?.into_iter().map(Plugin::from).collect();
 
-    let plugins: Vec<Plugin> = legacy_plugins.into_iter().chain(plugins).collect();
+    Ok(legacy_plugins.into_iter().chain(plugins).collect())
+}
+
+/// Get the plugins defined in the tmux config that are active on this machine
+pub fn get_plugins() -> Result<Vec<Plugin>> {
+    let plugins: Vec<Plugin> = get_all_plugins()?
+        .into_iter()
+        .filter(Plugin::is_active)
+        .collect();
 
-    utils::ensure_unique_by_key(&plugins, |p| p.name().to_owned()).map_err(|plugin| {
+    ensure_unique_names(&plugins)?;
+
+    Ok(plugins)
+}
+
+/// Plugins that are skipped may share their name with an active one, e.g. to use different forks
+/// on different machines, so only the names of active plugins need to be unique.
+fn ensure_unique_names(plugins: &[Plugin]) -> Result<()> {
+    utils::ensure_unique_by_key(plugins, |p| p.name().to_owned()).map_err(|plugin| {
         anyhow!(
             r#"More than one plugin with the name "{}" has been specified"#,
             plugin.name()
         )
-    })?;
+    })
+}
 
-    Ok(plugins)
+/// Get the active plugins with the given names, in the order of `names`
+pub fn get_plugins_by_name<T: AsRef<str>>(names: &[T]) -> Result<Vec<Plugin>> {
+    let (plugins, skipped): (Vec<_>, Vec<_>) =
+        get_all_plugins()?.into_iter().partition(Plugin::is_active);
+    ensure_unique_names(&plugins)?;
+
+    let mut plugin_map: HashMap<_, _> = plugins
+        .into_iter()
+        .map(|plugin| (plugin.name().to_owned(), plugin))
+        .collect();
+
+    names
+        .iter()
+        .map(|name| {
+            let name = name.as_ref();
+            if let Some(plugin) = plugin_map.remove(name) {
+                return Ok(plugin);
+            }
+            match skipped.iter().find(|plugin| plugin.name() == name) {
+                Some(plugin) => Err(anyhow!(
+                    r#"Plugin "{name}" is skipped on this machine: {}"#,
+                    plugin.skip_reason().unwrap_or_default()
+                )),
+                None => Err(anyhow!("Unknown plugin name: {}", name)),
+            }
+        })
+        .collect()
 }
 
 #[cached]
@@ -95,3 +203,51 @@ pub fn do_parallel() -> bool {
         default
     })
 }
+
+/// The default time after which loading a plugin is aborted. There is no timeout (`None`) unless
+/// `@tpm-load-timeout` is set.
+#[cached]
+pub fn load_timeout() -> Option<Duration> {
+    let val = tmux::get_option("@tpm-load-timeout")?;
+
+    match utils::parse_duration(&val) {
+        Some(Duration::ZERO) => None,
+        Some(timeout) => Some(timeout),
+        None => {
+            warn!(r#"Invalid value "{val}" for option `@tpm-load-timeout`. Not using a timeout"#);
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::test_utils::setup_dir;
+
+    use super::*;
+
+    #[test]
+    fn test_config_sourcing_itself() {
+        let dir = setup_dir(
+            "config-sourcing-itself",
+            &[
+                (
+                    "tmux.conf",
+                    "set -g @plugin 'user/a'\nsource-file '#{d:current_file}/*.conf'\n",
+                ),
+                ("other.conf", "set -g @plugin 'user/b'\n"),
+            ],
+        );
+
+        let mut warnings = vec![];
+        let specs =
+            get_specs_from_sourced_config(&dir.join("tmux.conf"), &mut vec![], &mut warnings)
+                .unwrap();
+        let names = specs
+            .iter()
+            .map(|(spec, _)| spec.name())
+            .collect::<Vec<_>>();
+        assert_eq!(names, ["a", "b"]);
+        assert!(warnings.is_empty());
+    }
+}
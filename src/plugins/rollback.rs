@@ -0,0 +1,195 @@
+use std::collections::BTreeMap;
+
+use anyhow::{Context, Result, bail};
+use cmd_lib::{FunChildren, spawn_with_output};
+use colored::Colorize;
+
+use crate::{
+    git,
+    lockfile::Lockfile,
+    operation_log::{self, Entry},
+    output,
+    plugin::Plugin,
+    prefix_lines::PrefixLines,
+    state,
+};
+
+use super::action_result::ActionResult;
+
+const STATE_NAME: &str = "rollback";
+
+/// The commits plugins were at before they were last changed by an update, by plugin name
+type RollbackState = BTreeMap<String, String>;
+
+/// Remember the previous commit of every plugin that has been changed by an update
+pub fn record(results: &[ActionResult]) -> Result<()> {
+    let mut rollback_state: RollbackState = state::load(STATE_NAME)?;
+
+    // Plugins whose hook failed after they have been updated are on the new commit as well
+    for result in results.iter().filter(|result| result.git_succeeded()) {
+        if let (Some(old), Some(new)) = (&result.old_commit, &result.new_commit)
+            && old != new
+        {
+            rollback_state.insert(result.plugin.name().to_owned(), old.to_owned());
+        }
+    }
+
+    state::save(STATE_NAME, &rollback_state)
+}
+
+/// Roll back the plugins with the given names to the commit they were at before their last
+/// update. If no names are given, all plugins with a recorded previous commit are rolled back.
+pub fn rollback<T: AsRef<str>>(names: &[T]) -> Result<Vec<ActionResult>> {
+    let mut rollback_state: RollbackState = state::load(STATE_NAME)?;
+
+    let plugins = if names.is_empty() {
+        super::get_plugins()?
+            .into_iter()
+            .filter(|plugin| rollback_state.contains_key(plugin.name()))
+            .collect()
+    } else {
+        super::get_plugins_by_name(names)?
+    };
+
+    let mut results = vec![];
+    for (plugin, commit) in rollback_points(plugins, &rollback_state)? {
+        let result = ActionResult::timed(|| rollback_plugin(plugin, &commit))?;
+        if result.result.is_ok() {
+            rollback_state.remove(result.plugin.name());
+        }
+        results.push(result);
+    }
+
+    state::save(STATE_NAME, &rollback_state)?;
+
+    operation_log::record(
+        results
+            .iter()
+            .map(|result| Entry::from_action("rollback", result)),
+    );
+
+    let mut lockfile = Lockfile::load()?;
+    for result in results.iter().filter(|result| result.result.is_ok()) {
+        lockfile.lock(&result.plugin)?;
+    }
+    lockfile.save()?;
+
+    if !output::is_json() {
+        if results.is_empty() {
+            eprintln!("-> Nothing to roll back");
+        }
+        results.iter().for_each(print_result);
+    }
+
+    Ok(results)
+}
+
+/// Get the commit to roll back each plugin to. All plugins are checked before any of them is
+/// rolled back, so that nothing is changed if one of them can't be rolled back.
+fn rollback_points(
+    plugins: Vec<Plugin>,
+    rollback_state: &RollbackState,
+) -> Result<Vec<(Plugin, String)>> {
+    let mut errors = vec![];
+    let mut points = vec![];
+
+    for plugin in plugins {
+        match rollback_state.get(plugin.name()) {
+            None => errors.push(format!(
+                r#"No previous revision recorded for plugin "{}""#,
+                plugin.name()
+            )),
+            Some(_) if !plugin.is_installed() => {
+                errors.push(format!(r#"Plugin "{}" is not installed"#, plugin.name()))
+            }
+            Some(commit) => {
+                let commit = commit.to_owned();
+                points.push((plugin, commit));
+            }
+        }
+    }
+
+    if !errors.is_empty() {
+        bail!("Nothing has been rolled back:\n{}", errors.join("\n"));
+    }
+    Ok(points)
+}
+
+fn rollback_plugin(plugin: Plugin, commit: &str) -> Result<ActionResult> {
+    let path = plugin.path();
+    let old_commit = git::head_commit(path).ok();
+
+    let mut proc: FunChildren = spawn_with_output!(
+        bash -c "
+            set -e;
+            cd $path;
+            git reset --quiet --hard $commit;
+            GIT_TERMINAL_PROMPT=0 git submodule update --init --recursive;
+        "
+    )
+    .context(format!(
+        r#"Failed to spawn subprocess for rolling back "{}". Is `git` installed?"#,
+        plugin
+    ))?;
+    let out = proc.wait_with_all();
+
+    Ok(ActionResult::new(plugin, out).with_commits(old_commit))
+}
+
+fn print_result(result: &ActionResult) {
+    let short = |commit: &Option<String>| {
+        commit
+            .as_deref()
+            .map(|commit| commit[..commit.len().min(7)].to_owned())
+            .unwrap_or_else(|| "?".into())
+    };
+
+    if result.result.is_ok() {
+        eprintln!(
+            "-> Rolled back {} ({} -> {})",
+            result.plugin.name(),
+            short(&result.old_commit),
+            short(&result.new_commit)
+        );
+        return;
+    }
+
+    eprintln!(
+        "{}",
+        format!(r#"Failed to roll back plugin "{}""#, result.plugin)
+            .bold()
+            .red()
+    );
+    eprintln!(
+        "{}",
+        result
+            .stderr
+            .prefix_lines(&"  err-out> ".bold().red().to_string())
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::spec::Spec;
+
+    use super::*;
+
+    #[test]
+    fn test_rollback_points() {
+        let plugins = ["user/ahiru-tpm-test-a", "user/ahiru-tpm-test-b"]
+            .map(|spec| Plugin::from(Spec::try_from(spec).unwrap()))
+            .into();
+        let rollback_state = RollbackState::from([("ahiru-tpm-test-a".into(), "0123abcd".into())]);
+
+        let Err(err) = rollback_points(plugins, &rollback_state) else {
+            panic!("Rolling back plugins without a rollback point should fail");
+        };
+        assert_eq!(
+            err.to_string(),
+            "Nothing has been rolled back:\n\
+             Plugin \"ahiru-tpm-test-a\" is not installed\n\
+             No previous revision recorded for plugin \"ahiru-tpm-test-b\""
+        );
+        assert!(rollback_points(vec![], &rollback_state).unwrap().is_empty());
+    }
+}
@@ -1,8 +1,23 @@
 use anyhow::Result;
 
-pub async fn sync() -> Result<()> {
-    super::install::install().await?;
-    super::clean::clean()?;
-    super::update::update_all().await?;
-    Ok(())
+use super::{
+    action_result::ActionResult,
+    clean::{CleanOptions, CleanResult},
+};
+
+pub struct SyncResults {
+    pub install: Vec<ActionResult>,
+    pub clean: CleanResult,
+    pub update: Vec<ActionResult>,
+}
+
+pub async fn sync(clean_options: CleanOptions) -> Result<SyncResults> {
+    let install = super::install::install().await?;
+    let clean = super::clean::clean(clean_options)?;
+    let update = super::update::update_all(false).await?;
+    Ok(SyncResults {
+        install,
+        clean,
+        update,
+    })
 }
@@ -0,0 +1,134 @@
+use anyhow::{Result, bail};
+use colored::Colorize;
+use serde::Serialize;
+
+use crate::{
+    operation_log::{self, Entry},
+    tmux::is_tmux_running,
+    tmux_changes::{self, Change, Snapshot, TrackedChanges},
+};
+
+#[derive(Serialize)]
+pub struct UnloadResult {
+    pub plugin: String,
+    /// Changes that have been reverted
+    pub reverted: Vec<Change>,
+    /// Changes that are kept, because the setting has been changed again since the plugin was
+    /// loaded
+    pub kept: Vec<Change>,
+    /// Changes that could not be reverted
+    pub failed: Vec<FailedRevert>,
+    /// Changes that might have been made by the plugin, but could not be attributed to it,
+    /// because it was loaded in parallel with other plugins. They are not reverted.
+    pub unattributed: Vec<Change>,
+}
+
+impl UnloadResult {
+    pub fn is_failed(&self) -> bool {
+        !self.failed.is_empty()
+    }
+}
+
+#[derive(Serialize)]
+pub struct FailedRevert {
+    #[serde(flatten)]
+    pub change: Change,
+    pub error: String,
+}
+
+/// Revert the key bindings, options and hooks the plugins changed when they were loaded
+pub fn unload(names: &[String]) -> Result<Vec<UnloadResult>> {
+    if !is_tmux_running() {
+        bail!("Tmux is not running");
+    }
+
+    let snapshot = Snapshot::take()?;
+    let mut tracked = TrackedChanges::load(&snapshot)?;
+    let mut results = vec![];
+
+    for name in names {
+        let (to_revert, kept) = tracked
+            .plugins
+            .remove(name)
+            .unwrap_or_default()
+            .into_iter()
+            .partition::<Vec<_>, _>(|change| {
+                snapshot.get(change.kind, &change.name) == change.after.as_ref()
+            });
+
+        // Every change is reverted on its own, so that one that fails doesn't keep the others from
+        // being reverted
+        let mut reverted = vec![];
+        let mut failed = vec![];
+        for change in to_revert {
+            match tmux_changes::run_command(&change.revert_command()) {
+                Ok(()) => reverted.push(change),
+                Err(err) => failed.push(FailedRevert {
+                    change,
+                    error: format!("{err:#}"),
+                }),
+            }
+        }
+
+        results.push(UnloadResult {
+            plugin: name.to_owned(),
+            reverted,
+            kept,
+            failed,
+            unattributed: tracked.unattributed.remove(name).unwrap_or_default(),
+        });
+    }
+
+    // Changes that failed to be reverted are not tracked anymore either, as they would only fail
+    // again
+    tracked.save()?;
+
+    operation_log::record(results.iter().map(Entry::from_unload));
+
+    Ok(results)
+}
+
+pub fn print_results(results: &[UnloadResult]) {
+    for result in results {
+        if result.reverted.is_empty()
+            && result.kept.is_empty()
+            && result.failed.is_empty()
+            && result.unattributed.is_empty()
+        {
+            eprintln!(
+                "-> {}: no changes to tmux have been tracked",
+                result.plugin.bold()
+            );
+            continue;
+        }
+
+        eprintln!(
+            "-> {}: reverted {} change(s)",
+            result.plugin.bold(),
+            result.reverted.len()
+        );
+        for change in &result.kept {
+            eprintln!(
+                "   {} {}",
+                change.name,
+                "was kept, because it has been changed since the plugin was loaded".dimmed()
+            );
+        }
+        for failed in &result.failed {
+            eprintln!(
+                "   {} {}",
+                failed.change.name,
+                format!("could not be reverted: {}", failed.error).red()
+            );
+        }
+        for change in &result.unattributed {
+            eprintln!(
+                "   {} {}",
+                change.name,
+                "was not reverted, because it could not be attributed to a single plugin loaded \
+                 in parallel"
+                    .yellow()
+            );
+        }
+    }
+}
@@ -1,4 +1,4 @@
-use std::collections::HashMap;
+use std::{collections::HashMap, io};
 
 use anyhow::{Context, Error, Result, anyhow};
 use cmd_lib::{FunChildren, spawn_with_output};
@@ -7,56 +7,117 @@ use futures::{StreamExt, stream::FuturesUnordered};
 use tokio::task;
 
 use crate::{
-    plugin::Plugin, prefix_lines::PrefixLines, progress_status::ProgressStatus,
+    git,
+    lockfile::Lockfile,
+    operation_log::{self, Entry},
+    output,
+    plugin::Plugin,
+    prefix_lines::PrefixLines,
+    progress_status::ProgressStatus,
     truncate_ellipsis::TruncateEllipsis,
 };
 
-use super::action_result::ActionResult;
+use super::{
+    action_result::ActionResult,
+    hooks::{self, Hook},
+    rollback,
+};
+
+/// Whether to apply updates or only check for them
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum UpdateMode {
+    Update,
+    DryRun,
+}
+
+impl UpdateMode {
+    fn from_dry_run(dry_run: bool) -> UpdateMode {
+        if dry_run {
+            UpdateMode::DryRun
+        } else {
+            UpdateMode::Update
+        }
+    }
+
+    fn verb(self) -> &'static str {
+        match self {
+            UpdateMode::Update => "Updating",
+            UpdateMode::DryRun => "Checking",
+        }
+    }
+
+    fn run(self, plugin: Plugin) -> Result<ActionResult> {
+        match self {
+            UpdateMode::Update => update_plugin(plugin),
+            UpdateMode::DryRun => check_plugin(plugin),
+        }
+    }
 
-pub async fn update_all() -> Result<()> {
+    fn success_status(self, result: &ActionResult) -> String {
+        match (self, result.commits.len()) {
+            (UpdateMode::Update, _) => "Done".into(),
+            (UpdateMode::DryRun, 0) => "Up to date".into(),
+            (UpdateMode::DryRun, 1) => "1 new commit".into(),
+            (UpdateMode::DryRun, n) => format!("{n} new commits"),
+        }
+    }
+}
+
+pub async fn update_all(dry_run: bool) -> Result<Vec<ActionResult>> {
     let plugins: Vec<Plugin> = super::get_plugins()?
         .into_iter()
         .filter(|plugin| plugin.is_installed())
         .collect();
 
-    update_plugins(plugins).await
+    update_plugins(plugins, UpdateMode::from_dry_run(dry_run)).await
 }
 
-pub async fn update_list<T: AsRef<str>>(names: &[T]) -> Result<()> {
-    let mut plugin_map: HashMap<_, _> = super::get_plugins()?
-        .into_iter()
-        .map(|plugin| (plugin.name().to_owned(), plugin))
-        .collect();
-
-    let plugins = names
-        .iter()
-        .map(|name| {
-            let name = name.as_ref();
-            plugin_map
-                .remove(name)
-                .context(format!("Unknown plugin name: {}", name))
-        })
-        .collect::<Result<Vec<_>>>()?;
+pub async fn update_list<T: AsRef<str>>(names: &[T], dry_run: bool) -> Result<Vec<ActionResult>> {
+    let plugins = super::get_plugins_by_name(names)?;
 
-    update_plugins(plugins).await
+    update_plugins(plugins, UpdateMode::from_dry_run(dry_run)).await
 }
 
-async fn update_plugins(plugins: Vec<Plugin>) -> Result<()> {
+async fn update_plugins(plugins: Vec<Plugin>, mode: UpdateMode) -> Result<Vec<ActionResult>> {
     let results = if super::do_parallel() {
-        update_parallel(plugins).await?
+        update_parallel(plugins, mode).await?
     } else {
-        update_sequential(plugins)?
+        update_sequential(plugins, mode)?
     };
 
-    for result in results {
-        if result.result.is_err() {
-            print_error(result);
+    if !output::is_json() {
+        for result in results.iter().filter(|result| result.result.is_err()) {
+            print_error(result, mode);
         }
     }
-    Ok(())
+
+    if !output::is_json() {
+        print_changelog(&results, mode);
+    }
+
+    if mode == UpdateMode::DryRun {
+        return Ok(results);
+    }
+
+    operation_log::record(
+        results
+            .iter()
+            .map(|result| Entry::from_action("update", result)),
+    );
+
+    rollback::record(&results)?;
+    hooks::record_pending(&results)?;
+
+    let mut lockfile = Lockfile::load()?;
+    for result in results.iter().filter(|result| result.git_succeeded()) {
+        lockfile.lock(&result.plugin)?;
+    }
+    lockfile.save()?;
+
+    Ok(results)
 }
 
-fn update_sequential(plugins: Vec<Plugin>) -> Result<Vec<ActionResult>> {
+fn update_sequential(plugins: Vec<Plugin>, mode: UpdateMode) -> Result<Vec<ActionResult>> {
     let mut results = vec![];
 
     let progress = ProgressStatus::new();
@@ -64,7 +125,7 @@ fn update_sequential(plugins: Vec<Plugin>) -> Result<Vec<ActionResult>> {
 
     for plugin in &plugins {
         let plugin_name = plugin.to_string().truncate_ellipsis(62);
-        let task_name = format!("Updating {plugin_name}");
+        let task_name = format!("{} {plugin_name}", mode.verb());
         let pt = progress.add_task(&task_name, "Waiting")?;
         plugin_progresses.insert(plugin.to_string(), pt);
     }
@@ -74,12 +135,14 @@ fn update_sequential(plugins: Vec<Plugin>) -> Result<Vec<ActionResult>> {
             .get(&plugin.to_string())
             .expect("Plugin progress should be in map");
 
-        pt.set_status("Updating")?;
+        pt.set_status(mode.verb())?;
 
-        let res = update_plugin(plugin)?;
+        let res = ActionResult::timed(|| mode.run(plugin))?;
 
         if res.result.is_ok() {
-            pt.set_success("Done")?;
+            pt.set_success(&mode.success_status(&res))?;
+        } else if res.hook_error.is_some() {
+            pt.set_failed("Updated, hook failed")?;
         } else {
             pt.set_failed("Failed")?;
         }
@@ -90,23 +153,25 @@ fn update_sequential(plugins: Vec<Plugin>) -> Result<Vec<ActionResult>> {
     Ok(results)
 }
 
-async fn update_parallel(plugins: Vec<Plugin>) -> Result<Vec<ActionResult>> {
+async fn update_parallel(plugins: Vec<Plugin>, mode: UpdateMode) -> Result<Vec<ActionResult>> {
     let mut tasks: FuturesUnordered<task::JoinHandle<std::result::Result<ActionResult, Error>>> =
         FuturesUnordered::new();
     let progress = ProgressStatus::new();
 
     for plugin in plugins {
         let plugin_name = plugin.to_string().truncate_ellipsis(62);
-        let task_name = format!("Updating {plugin_name}");
+        let task_name = format!("{} {plugin_name}", mode.verb());
         let pt = progress.add_task(&task_name, "Waiting")?;
 
         tasks.push(task::spawn(async move {
-            pt.set_status("Updating")?;
+            pt.set_status(mode.verb())?;
 
-            let res = update_plugin(plugin)?;
+            let res = ActionResult::timed(|| mode.run(plugin))?;
 
             if res.result.is_ok() {
-                pt.set_success("Done")?;
+                pt.set_success(&mode.success_status(&res))?;
+            } else if res.hook_error.is_some() {
+                pt.set_failed("Updated, hook failed")?;
             } else {
                 pt.set_failed("Failed")?;
             }
@@ -130,6 +195,42 @@ fn update_plugin(plugin: Plugin) -> Result<ActionResult> {
         return Err(anyhow!(r#"Plugin "{}" is not installed"#, plugin.name()));
     }
 
+    if plugin.is_local() {
+        return Ok(skip_local_plugin(plugin));
+    }
+
+    let old_commit = git::head_commit(plugin.path()).ok();
+
+    let result = if let Some(tag) = plugin.tag() {
+        let tag = tag.to_owned();
+        update_tag(plugin, &tag)?
+    } else if let Some(rev) = plugin.rev() {
+        let rev = rev.to_owned();
+        update_rev(plugin, &rev)?
+    } else {
+        update_branch(plugin)?
+    };
+
+    let mut result = result.with_commits(old_commit);
+    let mut changed = false;
+    if let (Some(old), Some(new)) = (&result.old_commit, &result.new_commit)
+        && old != new
+    {
+        // The changelog is informational only, so failing to read it does not fail the update
+        result.commits =
+            git::log(result.plugin.path(), &format!("{old}..{new}")).unwrap_or_default();
+        changed = true;
+    }
+
+    // Hooks only need to run when the plugin has actually changed, or when they failed last time
+    if changed || hooks::is_pending(result.plugin.name()) {
+        result = hooks::run(result, Hook::Update)?;
+    }
+
+    Ok(result)
+}
+
+fn update_branch(plugin: Plugin) -> Result<ActionResult> {
     let path = plugin.path();
 
     let mut proc: FunChildren = spawn_with_output!(
@@ -148,16 +249,171 @@ fn update_plugin(plugin: Plugin) -> Result<ActionResult> {
     Ok(ActionResult::new(plugin, out))
 }
 
-fn print_error(result: ActionResult) {
+/// Re-resolve the tag a plugin is pinned to, in case it has been moved upstream or changed in
+/// the config
+fn update_tag(plugin: Plugin, tag: &str) -> Result<ActionResult> {
+    // The tag is passed to git as an argument rather than through a shell, as it comes from the
+    // config
+    let tag_ref = format!("tags/{tag}");
+    run_git(
+        plugin,
+        &[
+            &["fetch", "--force", "origin", "tag", tag],
+            &["checkout", "--quiet", "--detach", &tag_ref],
+            &["submodule", "update", "--init", "--recursive"],
+        ],
+    )
+}
+
+/// Run git commands in the directory of a plugin one after another and collect their output.
+/// Stops at the first command that fails.
+fn run_git(plugin: Plugin, commands: &[&[&str]]) -> Result<ActionResult> {
+    let path = plugin.path().to_owned();
+    let mut stdout = String::new();
+    let mut stderr = String::new();
+
+    for args in commands {
+        let args = args.to_vec();
+        let mut proc: FunChildren = spawn_with_output!(GIT_TERMINAL_PROMPT=0 git -C $path $[args])
+            .context(format!(
+                r#"Failed to spawn subprocess for updating "{}". Is `git` installed?"#,
+                plugin
+            ))?;
+        let (status, out, err) = proc.wait_with_all();
+        hooks::append_output(&mut stdout, &out);
+        hooks::append_output(&mut stderr, &err);
+
+        if status.is_err() {
+            return Ok(ActionResult::new(plugin, (status, stdout, stderr)));
+        }
+    }
+
+    Ok(ActionResult::new(plugin, (Ok(()), stdout, stderr)))
+}
+
+/// Plugins pinned to a commit are only touched when the pinned commit has been changed in the
+/// config
+fn update_rev(plugin: Plugin, rev: &str) -> Result<ActionResult> {
+    let path = plugin.path();
+
+    let head = git::head_commit(path)?;
+    if git::resolve_commit(path, rev).is_some_and(|commit| commit == head) {
+        return Ok(ActionResult::new(
+            plugin,
+            (Ok(()), format!("Pinned to commit {rev}"), String::new()),
+        ));
+    }
+
+    let mut proc: FunChildren = spawn_with_output!(
+        bash -c "
+            set -e;
+            cd $path;
+            GIT_TERMINAL_PROMPT=0 git fetch origin;
+            git reset --hard $rev;
+            GIT_TERMINAL_PROMPT=0 git submodule update --init --recursive;
+        "
+    )
+    .context(format!(
+        r#"Failed to spawn subprocess for updating "{}". Is `git` installed?"#,
+        plugin
+    ))?;
+    let out = proc.wait_with_all();
+
+    Ok(ActionResult::new(plugin, out))
+}
+
+/// Local plugins are managed by the user, so they are never updated
+fn skip_local_plugin(plugin: Plugin) -> ActionResult {
+    let stdout = format!("Local plugin at {}", plugin.repo_url());
+    ActionResult::new(plugin, (Ok(()), stdout, String::new()))
+}
+
+/// Fetch a plugin and collect the commits it is behind, without touching the working tree
+fn check_plugin(plugin: Plugin) -> Result<ActionResult> {
+    if !plugin.is_installed() {
+        return Err(anyhow!(r#"Plugin "{}" is not installed"#, plugin.name()));
+    }
+
+    if plugin.is_local() {
+        return Ok(skip_local_plugin(plugin));
+    }
+
+    let path = plugin.path();
+    let head = git::head_commit(path).ok();
+
+    let (proc, target) = match (plugin.tag(), plugin.rev()) {
+        (Some(tag), _) => (
+            spawn_with_output!(
+                GIT_TERMINAL_PROMPT=0 git -C $path fetch --quiet --force origin tag $tag
+            ),
+            format!("tags/{tag}"),
+        ),
+        (None, Some(rev)) => {
+            let rev = rev.to_owned();
+            return Ok(ActionResult::new(
+                plugin,
+                (Ok(()), format!("Pinned to commit {rev}"), String::new()),
+            )
+            .with_commits(head));
+        }
+        (None, None) => (
+            spawn_with_output!(GIT_TERMINAL_PROMPT=0 git -C $path fetch --quiet),
+            "@{upstream}".to_owned(),
+        ),
+    };
+    let mut proc: FunChildren = proc.context(format!(
+        r#"Failed to spawn subprocess for checking "{}". Is `git` installed?"#,
+        plugin
+    ))?;
+    let out = proc.wait_with_all();
+
+    let mut result = ActionResult::new(plugin, out).with_commits(head);
+    if result.result.is_ok() {
+        match git::log(result.plugin.path(), &format!("HEAD..{target}")) {
+            Ok(commits) => result.commits = commits,
+            Err(err) => result.result = Err(io::Error::other(format!("{err:#}"))),
+        }
+    }
+
+    Ok(result)
+}
+
+/// Print the new commits of every plugin, i.e. the pulled commits after an update or the
+/// pending ones after a dry-run
+fn print_changelog(results: &[ActionResult], mode: UpdateMode) {
+    for result in results.iter().filter(|result| result.git_succeeded()) {
+        let summary = match (mode, result.commits.len()) {
+            (UpdateMode::Update, 0) => "Already up to date".to_owned(),
+            (UpdateMode::DryRun, 0) => "Up to date".to_owned(),
+            (_, 1) => "1 new commit".to_owned(),
+            (_, n) => format!("{n} new commits"),
+        };
+
+        eprintln!();
+        eprintln!(
+            "{} {summary}",
+            format!("==> {}:", result.plugin.name()).bold()
+        );
+        for commit in &result.commits {
+            eprintln!("    {} {}", commit.short_sha().yellow(), commit.subject);
+        }
+    }
+}
+
+fn print_error(result: &ActionResult, mode: UpdateMode) {
+    let action = match mode {
+        UpdateMode::Update => "update",
+        UpdateMode::DryRun => "check",
+    };
+
     eprintln!();
     eprintln!();
 
-    eprintln!(
-        "{}",
-        format!(r#"Failed to update plugin "{}""#, result.plugin)
-            .bold()
-            .red()
-    );
+    let title = match &result.hook_error {
+        Some(error) => format!(r#"Updated plugin "{}", but its {error}"#, result.plugin),
+        None => format!(r#"Failed to {action} plugin "{}""#, result.plugin),
+    };
+    eprintln!("{}", title.bold().red());
 
     eprint!(
         "{}",
@@ -2,7 +2,9 @@ use std::time::Duration;
 
 use anyhow::{Context, Result};
 use colored::Colorize;
-use indicatif::{MultiProgress, ProgressBar, ProgressStyle};
+use indicatif::{MultiProgress, ProgressBar, ProgressDrawTarget, ProgressStyle};
+
+use crate::output;
 
 pub struct ProgressStatus {
     multi_progress: MultiProgress,
@@ -16,7 +18,12 @@ impl Default for ProgressStatus {
 
 impl ProgressStatus {
     pub fn new() -> ProgressStatus {
-        let multi_progress = MultiProgress::new();
+        // Progress bars would only clutter structured output
+        let multi_progress = if output::is_json() {
+            MultiProgress::with_draw_target(ProgressDrawTarget::hidden())
+        } else {
+            MultiProgress::new()
+        };
 
         ProgressStatus { multi_progress }
     }
@@ -1,13 +1,30 @@
-use std::fmt::{self, Display};
+use std::{
+    fmt::{self, Display},
+    path::PathBuf,
+};
 
 use strum::{Display, EnumString};
 use url::Url;
 
+use crate::utils;
+
 #[derive(Debug, PartialEq, Clone)]
 pub enum RepoUrl {
     Short(String),
     Full(String),
     Alias(UrlAlias, String),
+    /// A plugin in a local directory, which is linked instead of cloned
+    Local(String),
+}
+
+impl RepoUrl {
+    /// The directory of a local plugin, with `~` expanded
+    pub fn local_path(&self) -> Option<PathBuf> {
+        match self {
+            RepoUrl::Local(path) => Some(utils::expand_home(path)),
+            _ => None,
+        }
+    }
 }
 
 #[derive(EnumString, Debug, PartialEq, Clone, Display)]
@@ -33,6 +50,7 @@ impl Display for RepoUrl {
             Short(url) => url,
             Full(url) => url,
             Alias(alias, url) => &format!("{}:{}", alias, url).to_string(),
+            Local(path) => &format!("path:{path}"),
         };
 
         write!(f, "{url}")
@@ -51,6 +69,11 @@ impl From<&RepoUrl> for Url {
             Alias(GitHub, url) => Url::parse(&format!("https://github.com/{url}.git")),
             Alias(GitLab, url) => Url::parse(&format!("https://gitlab.com/{url}.git")),
             Alias(BitBucket, url) => Url::parse(&format!("https://gitbucket.org/{url}.git")),
+            Local(_) => {
+                let path = value.local_path().expect("Local URL should have a path");
+                let path = std::path::absolute(&path).unwrap_or(path);
+                return Url::from_file_path(&path).expect("Absolute path should be a valid URL");
+            }
         }
         .expect("Url should be valid")
     }
@@ -0,0 +1,83 @@
+//! Reporting of failures to the user when Ahiru-TPM is run by tmux, e.g. via `run` in the tmux
+//! config, where stderr is either swallowed or only shown as "returned 1"
+
+use std::{fs, path::PathBuf, time::SystemTime};
+
+use anyhow::{Context, Result};
+use log::warn;
+
+use crate::{state, tmux, utils::shell_quote};
+
+/// Hook index used for reports that are shown once a client attaches. High enough to not clash
+/// with hooks set by the user.
+const HOOK_INDEX: u32 = 4207;
+
+const POPUP_TITLE: &str = " ahiru-tpm ";
+
+pub fn get_last_run_log_path() -> PathBuf {
+    state::get_state_dir().join("last-run.log")
+}
+
+/// Store the full log of the current run, replacing the log of the previous run
+pub fn save_last_run(command: &str, log: &str) -> Result<()> {
+    let path = get_last_run_log_path();
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)
+            .with_context(|| format!("Failed to create directory: {}", dir.display()))?;
+    }
+
+    let header = format!(
+        "ahiru-tpm {command} at {}",
+        humantime::format_rfc3339_seconds(SystemTime::now())
+    );
+    fs::write(&path, format!("{header}\n\n{log}"))
+        .with_context(|| format!("Failed writing log file: {}", path.display()))
+}
+
+/// Read the log of the last run. Returns `None` if nothing has been run yet.
+pub fn load_last_run() -> Result<Option<String>> {
+    let path = get_last_run_log_path();
+    if !path.exists() {
+        return Ok(None);
+    }
+
+    fs::read_to_string(&path)
+        .map(Some)
+        .with_context(|| format!("Failed reading log file: {}", path.display()))
+}
+
+/// Show a failure in tmux if Ahiru-TPM runs inside of it. The log of the last run is shown in a
+/// popup if tmux supports popups, otherwise `message` is shown in the status line. If no client
+/// is attached yet, which is the case while tmux starts, the report is shown once a client
+/// attaches.
+pub fn report_in_tmux(message: &str) {
+    if !tmux::is_inside_tmux() {
+        return;
+    }
+
+    let path = shell_quote(&get_last_run_log_path().to_string_lossy());
+    let popup = format!("less -R {path} 2>/dev/null || {{ cat {path}; read -r _; }}");
+    let message = format!("ahiru-tpm: {message} (run `ahiru-tpm log --last-run` for details)");
+    let has_popup = tmux::has_command("display-popup");
+
+    let result = match (tmux::has_clients(), has_popup) {
+        (true, true) => tmux::display_popup(POPUP_TITLE, &popup),
+        (true, false) => tmux::display_message(&message),
+        (false, true) => tmux::run_on_next_attach(
+            &format!(
+                "display-popup -E -w 80% -h 80% -T {} {}",
+                shell_quote(POPUP_TITLE),
+                shell_quote(&popup)
+            ),
+            HOOK_INDEX,
+        ),
+        (false, false) => tmux::run_on_next_attach(
+            &format!("display-message {}", shell_quote(&message)),
+            HOOK_INDEX,
+        ),
+    };
+
+    if let Err(err) = result {
+        warn!("Failed to report failure in tmux: {err:#}");
+    }
+}
@@ -1,18 +1,20 @@
 use std::collections::HashMap;
 
-use anyhow::{Context, Result, anyhow};
+use anyhow::{Context, Result, anyhow, bail};
 use derive_builder::Builder;
 use getset::Getters;
 use log::{error, warn};
-use pest::{Parser, iterators::Pairs};
+use pest::{Parser, error::LineColLocation, iterators::Pairs};
 use pest_derive::Parser;
 
 use crate::{
     attribute::Attribute,
     repo_url::{RepoUrl, UrlAlias},
+    utils,
 };
 
 #[derive(Builder, Debug, Getters, PartialEq, Clone)]
+#[builder(build_fn(validate = "Self::validate"))]
 pub struct Spec {
     #[getset(get = "pub")]
     name: String,
@@ -28,7 +30,25 @@ pub struct Spec {
     attributes: HashMap<Attribute, String>,
 }
 
+/// An attribute of a plugin spec that is not known and therefore ignored
+#[derive(Debug, Clone, PartialEq)]
+pub struct UnknownAttribute {
+    pub key: String,
+    /// Position of the attribute in the spec, in characters
+    pub offset: usize,
+}
+
 impl Spec {
+    /// Parse a plugin spec. Unknown attributes are returned instead of being warned about, so that
+    /// the caller can report them with their location.
+    pub fn parse(value: &str) -> Result<(Spec, Vec<UnknownAttribute>)> {
+        if value.is_empty() {
+            Err(anyhow!("Plugin spec must not be empty"))
+        } else {
+            parse_spec(value)
+        }
+    }
+
     pub fn try_from_legacy(value: &str) -> Result<Spec> {
         if value.contains(';') {
             Err(anyhow!(
@@ -47,6 +67,67 @@ impl Spec {
     }
 }
 
+impl SpecBuilder {
+    fn validate(&self) -> Result<(), String> {
+        let branch = self.branch.as_ref().and_then(Option::as_ref);
+        let attributes = self.attributes.as_ref();
+        let tag = attributes.and_then(|a| a.get(&Attribute::Tag));
+        let rev = attributes.and_then(|a| a.get(&Attribute::Rev));
+
+        if [branch.is_some(), tag.is_some(), rev.is_some()]
+            .iter()
+            .filter(|&&is_set| is_set)
+            .count()
+            > 1
+        {
+            return Err("Only one of `#branch`, `tag` and `rev` may be specified".into());
+        }
+
+        if matches!(self.url, Some(RepoUrl::Local(_))) && (tag.is_some() || rev.is_some()) {
+            return Err("`tag` and `rev` are not supported for local plugins".into());
+        }
+
+        if let Some(tag) = tag
+            && !is_valid_tag_name(tag)
+        {
+            return Err(format!("`tag` is not a valid tag name: {tag}"));
+        }
+
+        if let Some(rev) = rev
+            && (rev.len() < 4 || !rev.chars().all(|c| c.is_ascii_hexdigit()))
+        {
+            return Err(format!("`rev` must be a commit SHA: {rev}"));
+        }
+
+        if let Some(enabled) = attributes.and_then(|a| a.get(&Attribute::Enabled))
+            && utils::parse_bool(enabled).is_none()
+        {
+            return Err(format!("`enabled` must be `true` or `false`: {enabled}"));
+        }
+
+        Ok(())
+    }
+}
+
+/// Whether a tag name is valid according to the rules of `git check-ref-format`
+fn is_valid_tag_name(tag: &str) -> bool {
+    const FORBIDDEN: &[char] = &[' ', '~', '^', ':', '?', '*', '[', '\\'];
+
+    !tag.is_empty()
+        && tag != "@"
+        && !tag.starts_with('-')
+        && !tag.ends_with(['/', '.'])
+        && !tag.ends_with(".lock")
+        && !tag.contains("..")
+        && !tag.contains("@{")
+        && !tag
+            .chars()
+            .any(|c| c.is_ascii_control() || FORBIDDEN.contains(&c))
+        && tag
+            .split('/')
+            .all(|component| !component.is_empty() && !component.starts_with('.'))
+}
+
 #[derive(Parser)]
 #[grammar = "spec.pest"]
 struct SpecParser;
@@ -55,11 +136,11 @@ impl TryFrom<&str> for Spec {
     type Error = anyhow::Error;
 
     fn try_from(value: &str) -> Result<Self> {
-        if value.is_empty() {
-            Err(anyhow!("Plugin spec must not be empty"))
-        } else {
-            parse_spec(value)
+        let (spec, unknown_attributes) = Spec::parse(value)?;
+        for attribute in unknown_attributes {
+            warn!("Invalid attribute name: {}", attribute.key);
         }
+        Ok(spec)
     }
 }
 
@@ -71,32 +152,66 @@ impl TryFrom<String> for Spec {
     }
 }
 
-fn parse_spec(value: &str) -> std::result::Result<Spec, anyhow::Error> {
+/// Describe why parsing a spec failed. Also returns the position in the spec (in characters) at
+/// which parsing failed, if it is known.
+pub fn describe_parse_error(err: &anyhow::Error) -> (String, Option<usize>) {
+    match err.downcast_ref::<pest::error::Error<Rule>>() {
+        Some(err) => {
+            let col = match err.line_col {
+                LineColLocation::Pos((_, col)) | LineColLocation::Span((_, col), _) => col,
+            };
+            (err.variant.message().into_owned(), Some(col - 1))
+        }
+        None => (format!("{:#}", err.root_cause()), None),
+    }
+}
+
+fn parse_spec(value: &str) -> Result<(Spec, Vec<UnknownAttribute>)> {
     let mut builder = SpecBuilder::default();
+    let mut unknown_attributes = vec![];
 
     for pair in SpecParser::parse(Rule::spec, value)? {
-        parse_spec_pair(&mut builder, pair)?;
+        parse_spec_pair(&mut builder, &mut unknown_attributes, pair)?;
     }
 
-    builder
+    let spec = builder
         .build()
-        .context(format!("Building plugin spec from: {value}"))
+        .context(format!("Building plugin spec from: {value}"))?;
+
+    Ok((spec, unknown_attributes))
 }
 
-fn parse_spec_pair(builder: &mut SpecBuilder, pair: pest::iterators::Pair<'_, Rule>) -> Result<()> {
+fn parse_spec_pair(
+    builder: &mut SpecBuilder,
+    unknown_attributes: &mut Vec<UnknownAttribute>,
+    pair: pest::iterators::Pair<'_, Rule>,
+) -> Result<()> {
     match pair.as_rule() {
         Rule::url => {
             parse_url(builder, pair)?;
         }
 
+        Rule::local_url => {
+            parse_local_url(builder, pair)?;
+        }
+
         Rule::attribute => {
-            parse_attribute(builder, pair.into_inner()).context("Failed to parse attribute")?;
+            let (_, col) = pair.line_col();
+            if let Some(key) =
+                parse_attribute(builder, pair.into_inner()).context("Failed to parse attribute")?
+            {
+                unknown_attributes.push(UnknownAttribute {
+                    key,
+                    offset: col - 1,
+                });
+            }
         }
 
         Rule::EOI => return Ok(()),
 
         Rule::WHITESPACE
         | Rule::spec
+        | Rule::local_path
         | Rule::short_url
         | Rule::alias_url
         | Rule::prefix
@@ -154,6 +269,40 @@ fn parse_url(
     Ok(())
 }
 
+fn parse_local_url(
+    builder: &mut SpecBuilder,
+    pair: pest::iterators::Pair<'_, Rule>,
+) -> Result<(), anyhow::Error> {
+    let path = pair
+        .into_inner()
+        .find(|x| x.as_rule() == Rule::local_path)
+        .context("`local_url` should contain `local_path`")?
+        .as_str()
+        .to_owned();
+
+    // Relative paths are rejected, as it's unclear what they would be relative to. Paths in the
+    // home directory are expanded by `utils::expand_home`.
+    if !["/", "~/", "$HOME/", "${HOME}/"]
+        .iter()
+        .any(|prefix| path.starts_with(prefix))
+    {
+        bail!("Local plugin path must be absolute or start with `~/` or `$HOME/`: {path}");
+    }
+
+    // The plugin is named after the last component of its directory
+    let name = path
+        .trim_end_matches('/')
+        .rsplit('/')
+        .next()
+        .filter(|name| !["", "~", "$HOME", "${HOME}", ".", ".."].contains(name))
+        .with_context(|| format!("Failed to get plugin name from path: {path}"))?
+        .to_owned();
+
+    builder.url(RepoUrl::Local(path));
+    builder.name(name);
+    Ok(())
+}
+
 fn parse_short_url(
     builder: &mut SpecBuilder,
     pair: pest::iterators::Pair<'_, Rule>,
@@ -221,7 +370,11 @@ fn parse_full_url(
     Ok(())
 }
 
-fn parse_attribute(builder: &mut SpecBuilder, mut attribute_pairs: Pairs<'_, Rule>) -> Result<()> {
+/// Parse an attribute into the builder. Returns the key of the attribute if it is not known.
+fn parse_attribute(
+    builder: &mut SpecBuilder,
+    mut attribute_pairs: Pairs<'_, Rule>,
+) -> Result<Option<String>> {
     let key = attribute_pairs
         .find(|p| p.as_rule() == Rule::attr_key)
         .context("Attribute pairs should have an attribute key")?
@@ -256,13 +409,13 @@ fn parse_attribute(builder: &mut SpecBuilder, mut attribute_pairs: Pairs<'_, Rul
         _ => unreachable!(),
     };
 
-    if let Ok(key) = Attribute::try_from(key.as_ref()) {
-        builder.attribute((key, val));
-    } else {
-        warn!("Invalid attribute name: {key}");
+    match Attribute::try_from(key.as_ref()) {
+        Ok(key) => {
+            builder.attribute((key, val));
+            Ok(None)
+        }
+        Err(_) => Ok(Some(key)),
     }
-
-    Ok(())
 }
 
 #[cfg(test)]
@@ -293,6 +446,112 @@ mod tests {
         assert_eq!(Spec::try_from(value).unwrap(), expected_spec);
     }
 
+    #[test]
+    fn test_parse_pinned_tag() {
+        let value = "user_name/repo-name; tag = v2.1.0";
+        let expected_spec = Spec {
+            name: "repo-name".into(),
+            url: RepoUrl::Short("user_name/repo-name".into()),
+            branch: None,
+            attributes: HashMap::from([(Attribute::Tag, "v2.1.0".into())]),
+        };
+        assert_eq!(Spec::try_from(value).unwrap(), expected_spec);
+    }
+
+    #[test]
+    fn test_parse_multiple_attributes() {
+        let value = "user_name/repo-name; alias = name, parallel = false, tag = v2.1.0";
+        let expected_spec = Spec {
+            name: "repo-name".into(),
+            url: RepoUrl::Short("user_name/repo-name".into()),
+            branch: None,
+            attributes: HashMap::from([
+                (Attribute::Alias, "name".into()),
+                (Attribute::Parallel, "false".into()),
+                (Attribute::Tag, "v2.1.0".into()),
+            ]),
+        };
+        assert_eq!(Spec::try_from(value).unwrap(), expected_spec);
+    }
+
+    #[test]
+    fn test_parse_hooks() {
+        let value =
+            r#"user_name/repo-name; build = "cargo build --release", post_update='./setup'"#;
+        let expected_spec = Spec {
+            name: "repo-name".into(),
+            url: RepoUrl::Short("user_name/repo-name".into()),
+            branch: None,
+            attributes: HashMap::from([
+                (Attribute::Build, "cargo build --release".into()),
+                (Attribute::PostUpdate, "./setup".into()),
+            ]),
+        };
+        assert_eq!(Spec::try_from(value).unwrap(), expected_spec);
+    }
+
+    #[test]
+    fn test_parse_local_path() {
+        for (value, path) in [
+            ("path:~/src/my-plugin/", "~/src/my-plugin/"),
+            ("file:///src/my-plugin; parallel=false", "/src/my-plugin"),
+            ("path:$HOME/src/my-plugin", "$HOME/src/my-plugin"),
+            ("path:${HOME}/src/my-plugin", "${HOME}/src/my-plugin"),
+        ] {
+            let spec = Spec::try_from(value).unwrap();
+            assert_eq!(spec.name(), "my-plugin");
+            assert_eq!(spec.url(), &RepoUrl::Local(path.into()));
+        }
+
+        assert!(Spec::try_from("path:~").is_err());
+        assert!(Spec::try_from("path:$HOME/").is_err());
+        assert!(Spec::try_from("path:$HOMEDIR/my-plugin").is_err());
+        assert!(Spec::try_from("path:./my-plugin").is_err());
+        assert!(Spec::try_from("path:src/my-plugin").is_err());
+        assert!(Spec::try_from("path:~/src/my-plugin; tag=v1").is_err());
+    }
+
+    #[test]
+    fn test_is_valid_tag_name() {
+        for tag in ["v1.2.3", "release/2024-01", "v1_rc"] {
+            assert!(is_valid_tag_name(tag), "{tag} should be valid");
+        }
+        for tag in [
+            "", "@", "-v1", "v1.", "v1/", "v1.lock", "v1..2", "v@{1}", "v 1", "v1^", "v1~1",
+            "a//b", ".v1", "a/.b", "v1\tx",
+        ] {
+            assert!(!is_valid_tag_name(tag), "{tag} should be invalid");
+        }
+    }
+
+    #[test]
+    fn test_parse_enabled() {
+        let spec = Spec::try_from("user/repo; enabled = false").unwrap();
+        assert_eq!(
+            spec.attributes().get(&Attribute::Enabled),
+            Some(&"false".to_owned())
+        );
+
+        let err = Spec::try_from("user/repo; enabled = nope").unwrap_err();
+        assert!(format!("{err:#}").contains("`enabled` must be `true` or `false`: nope"));
+    }
+
+    #[test]
+    fn test_should_error_on_branch_and_rev() {
+        let value = "user_name/repo-name#main; rev = 0123abcd";
+        let err = Spec::try_from(value).unwrap_err();
+        assert!(
+            format!("{err:#}").contains("Only one of `#branch`, `tag` and `rev` may be specified")
+        );
+    }
+
+    #[test]
+    fn test_should_error_on_invalid_rev() {
+        let value = "user_name/repo-name; rev = main";
+        let err = Spec::try_from(value).unwrap_err();
+        assert!(format!("{err:#}").contains("`rev` must be a commit SHA: main"));
+    }
+
     #[test]
     fn test_should_error_on_empty_value() {
         let value = "";
@@ -300,4 +559,25 @@ mod tests {
         let err = result.unwrap_err();
         assert_eq!(format!("{err}"), "Plugin spec must not be empty");
     }
+
+    #[test]
+    fn test_parse_unknown_attributes() {
+        let value = "user_name/repo-name; tag=v1, foo=bar";
+        let (spec, unknown_attributes) = Spec::parse(value).unwrap();
+        assert_eq!(spec.attributes().get(&Attribute::Tag).unwrap(), "v1");
+        assert_eq!(
+            unknown_attributes,
+            vec![UnknownAttribute {
+                key: "foo".into(),
+                offset: 29,
+            }]
+        );
+    }
+
+    #[test]
+    fn test_describe_parse_error() {
+        let err = Spec::try_from("user_name/repo-name;").unwrap_err();
+        let (_, offset) = describe_parse_error(&err);
+        assert_eq!(offset, Some(20));
+    }
 }
@@ -0,0 +1,40 @@
+use std::{fs, path::PathBuf};
+
+use anyhow::{Context, Result};
+use serde::{Serialize, de::DeserializeOwned};
+
+/// Get the directory in which Ahiru-TPM persists its state between runs
+pub fn get_state_dir() -> PathBuf {
+    xdir::state()
+        .expect("XDG paths should be possible to build")
+        .join("ahiru-tpm")
+}
+
+fn get_state_file(name: &str) -> PathBuf {
+    get_state_dir().join(format!("{name}.json"))
+}
+
+/// Load a state file. A missing state file results in the default value.
+pub fn load<T: DeserializeOwned + Default>(name: &str) -> Result<T> {
+    let path = get_state_file(name);
+
+    if !path.exists() {
+        return Ok(T::default());
+    }
+
+    let content = fs::read_to_string(&path)
+        .with_context(|| format!("Failed reading state file: {}", path.display()))?;
+    serde_json::from_str(&content)
+        .with_context(|| format!("Failed to parse state file: {}", path.display()))
+}
+
+pub fn save<T: Serialize>(name: &str, value: &T) -> Result<()> {
+    let dir = get_state_dir();
+    fs::create_dir_all(&dir)
+        .with_context(|| format!("Failed to create directory: {}", dir.display()))?;
+
+    let path = get_state_file(name);
+    let content = serde_json::to_string_pretty(value).context("Failed to serialize state")?;
+    fs::write(&path, content + "\n")
+        .with_context(|| format!("Failed writing state file: {}", path.display()))
+}
@@ -0,0 +1,14 @@
+use std::{env, fs, path::PathBuf};
+
+/// Create a fresh directory for a test that contains the given files
+pub fn setup_dir(test_name: &str, files: &[(&str, &str)]) -> PathBuf {
+    let dir = env::temp_dir().join(format!("ahiru-tpm-test-{test_name}"));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    for (name, content) in files {
+        let path = dir.join(name);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, content).unwrap();
+    }
+    dir
+}
@@ -1,44 +1,157 @@
-use std::{env::current_dir, fs, path::PathBuf};
+use std::{
+    env::{self, current_dir},
+    fs,
+    os::unix::process::CommandExt,
+    path::{self, Path, PathBuf},
+    process::{Command, Stdio},
+};
 
 use anyhow::{Context, Result, anyhow};
 use cached::proc_macro::cached;
-use cmd_lib::run_fun;
+use cmd_lib::{run_cmd, run_fun};
+use once_cell::sync::OnceCell;
 
-use crate::key_bindings;
+use crate::{key_bindings, utils};
 
 pub fn get_option(name: &str) -> Option<String> {
     run_fun!(tmux show-option -vg $name 2>/dev/null).ok()
 }
 
-pub fn get_existing_config_paths() -> Vec<PathBuf> {
-    let mut configs = vec![];
+const SYSTEM_CONFIG_PATH: &str = "/etc/tmux.conf";
 
-    let system_config = PathBuf::from("/etc/tmux.conf");
-    if system_config.exists() {
-        configs.push(system_config);
+static CONFIG_PATHS_OVERRIDE: OnceCell<Vec<PathBuf>> = OnceCell::new();
+
+/// Override the config files to read (e.g. from the `--config` flag). Only the first call has an
+/// effect and it must happen before the config paths are first used.
+pub fn set_config_paths(paths: Vec<PathBuf>) {
+    let paths = paths
+        .into_iter()
+        .map(|path| path::absolute(&path).unwrap_or(path))
+        .collect();
+    let _ = CONFIG_PATHS_OVERRIDE.set(paths);
+}
+
+/// Get the config files to read plugins from. In order of precedence, these are:
+///
+/// 1. The paths set via `set_config_paths` (i.e. the `--config` flag)
+/// 2. The colon-separated paths in the `TMUX_CONF` environment variable
+/// 3. The config files the running tmux server has loaded
+/// 4. The config files tmux loads by default
+///
+/// Except for explicitly set paths, only existing files are returned.
+#[cached]
+pub fn get_config_paths() -> Vec<PathBuf> {
+    if let Some(paths) = CONFIG_PATHS_OVERRIDE.get() {
+        return paths.to_owned();
     }
 
-    let home_config = xdir::home()
-        .expect("$HOME should be set")
-        .join(".tmux.conf");
-    if home_config.exists() {
-        configs.push(home_config);
+    let paths = env::var("TMUX_CONF")
+        .ok()
+        .map(|paths| {
+            paths
+                .split(':')
+                .filter(|path| !path.is_empty())
+                .map(utils::expand_home)
+                .collect()
+        })
+        .or_else(get_loaded_config_paths)
+        .unwrap_or_else(get_default_config_paths);
+
+    paths.into_iter().filter(|path| path.exists()).collect()
+}
+
+/// Get the config files loaded by the running tmux server
+fn get_loaded_config_paths() -> Option<Vec<PathBuf>> {
+    let paths = run_fun!(tmux display -p "#{config_files}" 2>/dev/null).ok()?;
+    let paths: Vec<PathBuf> = paths
+        .split(',')
+        .filter(|path| !path.is_empty())
+        .map(PathBuf::from)
+        .collect();
+
+    (!paths.is_empty()).then_some(paths)
+}
+
+/// Get the config files tmux loads by default, mirroring the default of tmux's `TMUX_CONF` build
+/// option
+fn get_default_config_paths() -> Vec<PathBuf> {
+    let home = xdir::home().expect("$HOME should be set");
+
+    let mut paths = vec![PathBuf::from(SYSTEM_CONFIG_PATH), home.join(".tmux.conf")];
+    if let Some(config_home) = env::var_os("XDG_CONFIG_HOME").filter(|dir| !dir.is_empty()) {
+        paths.push(PathBuf::from(config_home).join("tmux/tmux.conf"));
     }
+    paths.push(home.join(".config/tmux/tmux.conf"));
 
-    let user_config = xdir::config()
+    utils::dedup_by_key(paths, PathBuf::clone)
+}
+
+fn get_xdg_config_path() -> PathBuf {
+    xdir::config()
         .expect("XDG paths should be possible to build")
-        .join("tmux/tmux.conf");
-    if user_config.exists() {
-        configs.push(user_config);
-    }
+        .join("tmux/tmux.conf")
+}
 
-    configs
+/// Get the path of the user config, which is the first config that is not the system config.
+/// Falls back to the XDG config if there is none.
+pub fn get_user_config_path() -> PathBuf {
+    get_config_paths()
+        .into_iter()
+        .find(|path| path != Path::new(SYSTEM_CONFIG_PATH))
+        .unwrap_or_else(get_xdg_config_path)
 }
 
+/// Get the path of the lockfile, which lives next to the user config
+pub fn get_lockfile_path() -> PathBuf {
+    get_user_config_path()
+        .parent()
+        .expect("Config file should have a parent")
+        .join("ahiru-tpm.lock")
+}
+
+/// Get a variable from the global tmux environment
+pub fn get_environment(name: &str) -> Option<String> {
+    run_fun!(tmux show-environment -g $name 2>/dev/null)
+        .ok()
+        .and_then(|line| Some(line.split_once('=')?.1.to_owned()))
+}
+
+static PLUGINS_DIR_OVERRIDE: OnceCell<PathBuf> = OnceCell::new();
+
+/// Override the plugins dir (e.g. from the `--plugins-dir` flag). Only the first call has an
+/// effect and it must happen before the plugins dir is first used.
+pub fn set_plugins_dir(path: PathBuf) {
+    let _ = PLUGINS_DIR_OVERRIDE.set(path);
+}
+
+/// Get the directory plugins are installed to. In order of precedence, this is:
+///
+/// 1. The path set via `set_plugins_dir` (i.e. the `--plugins-dir` flag)
+/// 2. The `@tpm-plugins-dir` tmux option
+/// 3. The `TMUX_PLUGIN_MANAGER_PATH` environment variable, either of the process or of the tmux
+///    server (for compatibility with TPM)
+/// 4. `~/.tmux/plugins` if it exists, i.e. the directory TPM installs plugins to by default
+/// 5. `$XDG_DATA_HOME/tmux/plugins`
+#[cached]
 pub fn get_plugins_dir() -> PathBuf {
-    xdir::data()
-        .expect("XDG paths should be possible to build")
-        .join("tmux/plugins")
+    if let Some(path) = PLUGINS_DIR_OVERRIDE.get() {
+        return path.to_owned();
+    }
+
+    get_option("@tpm-plugins-dir")
+        .or_else(|| env::var("TMUX_PLUGIN_MANAGER_PATH").ok())
+        .or_else(|| get_environment("TMUX_PLUGIN_MANAGER_PATH"))
+        .filter(|path| !path.is_empty())
+        .map(|path| utils::expand_home(&path))
+        .or_else(|| {
+            let tpm_dir = utils::expand_home("~/.tmux/plugins");
+            tpm_dir.is_dir().then_some(tpm_dir)
+        })
+        .unwrap_or_else(|| {
+            xdir::data()
+                .expect("XDG paths should be possible to build")
+                .join("tmux/plugins")
+        })
 }
 
 pub fn ensure_plugins_dir_exists() -> Result<PathBuf> {
@@ -69,6 +182,87 @@ pub fn get_start_path() -> Result<PathBuf> {
     }
 }
 
+/// Get the command bound to a key in the prefix key table
+pub fn get_key_binding(key: &str) -> Option<String> {
+    run_fun!(tmux list-keys -T prefix $key 2>/dev/null)
+        .ok()
+        .filter(|binding| !binding.is_empty())
+}
+
+/// Show a message in the status line of the current client
+pub fn display_message(message: &str) -> Result<()> {
+    run_cmd!(tmux display-message $message 2>/dev/null)?;
+    Ok(())
+}
+
+/// Run a shell command in a popup on the current client. Doesn't wait for the popup to be
+/// closed.
+pub fn display_popup(title: &str, command: &str) -> Result<()> {
+    // `display-popup` blocks until the popup is closed, so it runs in its own process group to
+    // outlive Ahiru-TPM
+    Command::new("tmux")
+        .args([
+            "display-popup",
+            "-E",
+            "-w",
+            "80%",
+            "-h",
+            "80%",
+            "-T",
+            title,
+            command,
+        ])
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .process_group(0)
+        .spawn()
+        .context("Failed to run `tmux display-popup`")?;
+    Ok(())
+}
+
+/// Run tmux commands once, when the next client attaches. `index` is the index of the hooks that
+/// are used for this, so that hooks set by the user are kept.
+pub fn run_on_next_attach(commands: &str, index: u32) -> Result<()> {
+    let hooks =
+        ["client-attached", "client-session-changed"].map(|hook| format!("{hook}[{index}]"));
+    let unset = hooks
+        .iter()
+        .map(|hook| format!("set-hook -gu {hook}"))
+        .collect::<Vec<_>>()
+        .join("; ");
+    let commands = format!("{unset}; {commands}");
+
+    for hook in hooks {
+        run_cmd!(tmux set-hook -g $hook $commands)?;
+    }
+    Ok(())
+}
+
+/// Whether Ahiru-TPM runs inside of tmux, e.g. via `run` in the tmux config or in a pane
+pub fn is_inside_tmux() -> bool {
+    env::var_os("TMUX").is_some()
+}
+
+/// Whether any client is attached to the tmux server
+pub fn has_clients() -> bool {
+    run_fun!(tmux list-clients -F "#{client_name}" 2>/dev/null)
+        .is_ok_and(|clients| !clients.is_empty())
+}
+
+/// Whether the running tmux version supports a command
+pub fn has_command(name: &str) -> bool {
+    run_fun!(tmux list-commands $name 2>/dev/null).is_ok()
+}
+
+/// Expand a tmux format string. Returns `None` if tmux is not running.
+pub fn expand_format(format: &str) -> Option<String> {
+    if !is_tmux_running() {
+        return None;
+    }
+    run_fun!(tmux display -p $format 2>/dev/null).ok()
+}
+
 #[cached]
 pub fn is_tmux_running() -> bool {
     run_fun!(tmux display -p).is_ok()
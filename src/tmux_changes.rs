@@ -0,0 +1,536 @@
+//! Tracking of the key bindings, options and hooks that plugins change in the running tmux server
+//! while they are loaded, so that these changes can be reverted when a plugin is unloaded
+
+use std::{
+    collections::BTreeMap,
+    io::Write,
+    path::PathBuf,
+    process::{Command, Stdio},
+};
+
+use anyhow::{Context, Result, bail};
+use cmd_lib::run_fun;
+use log::warn;
+use serde::{Deserialize, Serialize};
+
+use crate::{plugin::Plugin, state, utils::shell_quote};
+
+const STATE_NAME: &str = "tmux-changes";
+
+/// Printed between the sections of a snapshot to tell them apart
+const SECTION_MARKER: &str = "=== ahiru-tpm ===";
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "kebab-case")]
+pub enum Kind {
+    KeyBinding,
+    Option,
+    WindowOption,
+    ServerOption,
+    Hook,
+}
+
+impl Kind {
+    const ALL: [Kind; 5] = [
+        Kind::KeyBinding,
+        Kind::Option,
+        Kind::WindowOption,
+        Kind::ServerOption,
+        Kind::Hook,
+    ];
+
+    /// The tmux command that lists all settings of this kind
+    fn list_command(self) -> &'static [&'static str] {
+        match self {
+            Kind::KeyBinding => &["list-keys"],
+            Kind::Option => &["show-options", "-g"],
+            Kind::WindowOption => &["show-options", "-gw"],
+            Kind::ServerOption => &["show-options", "-s"],
+            Kind::Hook => &["show-hooks", "-g"],
+        }
+    }
+
+    /// Split a line of `list_command` into the name and the value of the setting
+    fn parse(self, line: &str) -> Option<(String, String)> {
+        match self {
+            // E.g. `bind-key -r -T prefix Up select-pane -U`. The value is the whole line, which
+            // is the command that creates the key binding.
+            Kind::KeyBinding => {
+                let mut tokens = line.split_whitespace().skip(1);
+                let mut table = None;
+                loop {
+                    match tokens.next()? {
+                        "-T" => table = tokens.next(),
+                        flag if flag.starts_with('-') && flag.len() == 2 => (),
+                        key => return Some((format!("{} {key}", table?), line.to_owned())),
+                    }
+                }
+            }
+            // E.g. `status-left "[#S] "` or `after-new-window[0] run-shell /path/hook.sh`. Hooks
+            // without commands are listed by name only.
+            _ => line
+                .split_once(char::is_whitespace)
+                .map(|(name, value)| (name.to_owned(), value.trim_start().to_owned())),
+        }
+    }
+
+    /// Get the tmux command that sets a setting to a value of `parse`
+    fn set_command(self, name: &str, value: &str) -> String {
+        match self {
+            Kind::KeyBinding => value.to_owned(),
+            // Option values are listed quoted already
+            Kind::Option => format!("set-option -g {name} {value}"),
+            Kind::WindowOption => format!("set-option -gw {name} {value}"),
+            Kind::ServerOption => format!("set-option -s {name} {value}"),
+            // Hook commands are listed as they are, without quotes
+            Kind::Hook => format!("set-hook -g {name} {}", shell_quote(value)),
+        }
+    }
+
+    /// Get the tmux command that removes a setting
+    fn unset_command(self, name: &str) -> String {
+        match self {
+            Kind::KeyBinding => match name.split_once(' ') {
+                Some((table, key)) => format!("unbind-key -T {table} {key}"),
+                None => format!("unbind-key {name}"),
+            },
+            Kind::Option => format!("set-option -gu {name}"),
+            Kind::WindowOption => format!("set-option -gwu {name}"),
+            Kind::ServerOption => format!("set-option -su {name}"),
+            Kind::Hook => format!("set-hook -gu {name}"),
+        }
+    }
+}
+
+/// A key binding, option or hook that has been changed by loading a plugin
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Change {
+    pub kind: Kind,
+    /// Name of the option or hook, or table and key of the key binding
+    pub name: String,
+    /// The value before the plugin was loaded, `None` if it was not set. The value of a key
+    /// binding is the command that creates it.
+    pub before: Option<String>,
+    /// The value after the plugin was loaded, `None` if the plugin removed it
+    pub after: Option<String>,
+}
+
+impl Change {
+    /// Get the tmux command that restores the setting from before the plugin was loaded
+    pub fn revert_command(&self) -> String {
+        match &self.before {
+            Some(value) => self.kind.set_command(&self.name, value),
+            None => self.kind.unset_command(&self.name),
+        }
+    }
+
+    fn mentions(&self, text: &str) -> bool {
+        [&self.before, &self.after]
+            .into_iter()
+            .flatten()
+            .any(|value| value.contains(text))
+    }
+}
+
+/// All key bindings, options and hooks of a tmux server at one point in time
+pub struct Snapshot {
+    /// PID of the tmux server, to tell whether tmux has been restarted since changes were tracked
+    pub server_pid: String,
+    settings: BTreeMap<(Kind, String), String>,
+}
+
+impl Snapshot {
+    /// Take a snapshot of the running tmux server. All settings are listed by a single tmux
+    /// command to keep this fast.
+    pub fn take() -> Result<Snapshot> {
+        let mut args = vec!["display-message", "-p", "#{pid}"];
+        for kind in Kind::ALL {
+            args.extend([";", "display-message", "-p", SECTION_MARKER, ";"]);
+            args.extend(kind.list_command());
+        }
+
+        let output =
+            run_fun!(tmux $[args] 2>/dev/null).context("Failed to list the settings of tmux")?;
+        Snapshot::parse(&output)
+    }
+
+    fn parse(output: &str) -> Result<Snapshot> {
+        let mut lines = output.lines();
+        let server_pid = lines.next().unwrap_or_default().trim().to_owned();
+
+        let mut settings = BTreeMap::new();
+        let mut kinds = Kind::ALL.into_iter();
+        let mut kind = None;
+        for line in lines {
+            if line == SECTION_MARKER {
+                kind = kinds.next();
+            } else if let Some(kind) = kind
+                && let Some((name, value)) = kind.parse(line)
+            {
+                settings.insert((kind, name), value);
+            }
+        }
+
+        if kinds.next().is_some() {
+            bail!("Unexpected output when listing the settings of tmux");
+        }
+
+        Ok(Snapshot {
+            server_pid,
+            settings,
+        })
+    }
+
+    pub fn get(&self, kind: Kind, name: &str) -> Option<&String> {
+        self.settings.get(&(kind, name.to_owned()))
+    }
+
+    /// Get all settings that differ from the ones of an earlier snapshot
+    fn diff(&self, earlier: &Snapshot) -> Vec<Change> {
+        let changed = |(key, value): (&(Kind, String), &String)| {
+            let other = earlier.settings.get(key);
+            (other != Some(value)).then(|| Change {
+                kind: key.0,
+                name: key.1.clone(),
+                before: other.cloned(),
+                after: Some(value.clone()),
+            })
+        };
+        let removed = |(key, value): (&(Kind, String), &String)| {
+            (!self.settings.contains_key(key)).then(|| Change {
+                kind: key.0,
+                name: key.1.clone(),
+                before: Some(value.clone()),
+                after: None,
+            })
+        };
+
+        self.settings
+            .iter()
+            .filter_map(changed)
+            .chain(earlier.settings.iter().filter_map(removed))
+            .collect()
+    }
+}
+
+/// The changes of all loaded plugins, as stored in the state dir
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct TrackedChanges {
+    pub server_pid: String,
+    pub plugins: BTreeMap<String, Vec<Change>>,
+    /// Changes made while several plugins were loaded in parallel that could not be attributed to
+    /// one of them, by the plugins they might belong to. They are not reverted on unload.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub unattributed: BTreeMap<String, Vec<Change>>,
+}
+
+impl TrackedChanges {
+    /// Load the changes tracked for the tmux server a snapshot has been taken of. Changes tracked
+    /// for a previous tmux server are dropped.
+    pub fn load(snapshot: &Snapshot) -> Result<TrackedChanges> {
+        let tracked: TrackedChanges = state::load(STATE_NAME)?;
+        if tracked.server_pid != snapshot.server_pid {
+            return Ok(TrackedChanges {
+                server_pid: snapshot.server_pid.clone(),
+                ..Default::default()
+            });
+        }
+        Ok(tracked)
+    }
+
+    pub fn save(&self) -> Result<()> {
+        state::save(STATE_NAME, self)
+    }
+
+    /// Add the changes of loading a plugin. When a plugin is loaded again, the setting from
+    /// before it was loaded for the first time is kept.
+    fn add(&mut self, plugin: &str, changes: Vec<Change>) {
+        merge(&mut self.plugins, plugin, changes);
+    }
+
+    /// Add the changes of loading several plugins in parallel. A change can only be attributed to
+    /// a plugin if it mentions a file in the directory of exactly one of them. The other changes
+    /// are added as unattributed to every plugin they might belong to. Returns the number of
+    /// unattributed changes.
+    fn add_parallel(&mut self, plugins: &[&Plugin], changes: Vec<Change>) -> usize {
+        let dirs = plugins
+            .iter()
+            .map(|plugin| (plugin.name(), plugin_dirs(plugin)))
+            .collect::<Vec<_>>();
+
+        let mut unattributed = 0;
+        for change in changes {
+            let owners = dirs
+                .iter()
+                .filter(|(_, dirs)| {
+                    dirs.iter()
+                        .any(|dir| change.mentions(&format!("{}/", dir.display())))
+                })
+                .map(|(name, _)| *name)
+                .collect::<Vec<_>>();
+
+            if let [owner] = owners[..] {
+                self.add(owner, vec![change]);
+                continue;
+            }
+
+            unattributed += 1;
+            let candidates = if owners.is_empty() {
+                dirs.iter().map(|(name, _)| *name).collect()
+            } else {
+                owners
+            };
+            for name in candidates {
+                merge(&mut self.unattributed, name, vec![change.clone()]);
+            }
+        }
+        unattributed
+    }
+}
+
+/// Merge changes into the ones tracked for a plugin, keeping the setting from before the first
+/// change. Changes that have been undone are dropped.
+fn merge(tracked: &mut BTreeMap<String, Vec<Change>>, plugin: &str, changes: Vec<Change>) {
+    let entry = tracked.entry(plugin.to_owned()).or_default();
+
+    for change in changes {
+        match entry
+            .iter_mut()
+            .find(|tracked| tracked.kind == change.kind && tracked.name == change.name)
+        {
+            Some(tracked) => tracked.after = change.after,
+            None => entry.push(change),
+        }
+    }
+
+    entry.retain(|change| change.before != change.after);
+    if entry.is_empty() {
+        tracked.remove(plugin);
+    }
+}
+
+/// Tracks the changes of plugins while they are loaded by taking a snapshot after loading each
+/// group of plugins
+pub struct ChangeTracker {
+    snapshot: Snapshot,
+    tracked: TrackedChanges,
+}
+
+impl ChangeTracker {
+    /// Start tracking changes. Returns `None` if tmux can't be queried, in which case loading
+    /// works as usual without tracking changes.
+    pub fn start() -> Option<ChangeTracker> {
+        let start = || -> Result<ChangeTracker> {
+            let snapshot = Snapshot::take()?;
+            let tracked = TrackedChanges::load(&snapshot)?;
+            Ok(ChangeTracker { snapshot, tracked })
+        };
+
+        start()
+            .inspect_err(|err| warn!("Failed to track changes of plugins: {err:#}"))
+            .ok()
+    }
+
+    /// Attribute all changes since the last snapshot to the plugins that have been loaded in the
+    /// meantime. When several plugins have been loaded in parallel, a change can only be
+    /// attributed to a plugin if it mentions a file in the directory of exactly one of them. The
+    /// other changes are reported by `unload` without being reverted.
+    pub fn track(&mut self, plugins: &[&Plugin]) {
+        if plugins.is_empty() {
+            return;
+        }
+
+        let snapshot = match Snapshot::take() {
+            Ok(snapshot) => snapshot,
+            Err(err) => {
+                warn!("Failed to track changes of plugins: {err:#}");
+                return;
+            }
+        };
+        let changes = snapshot.diff(&self.snapshot);
+        self.snapshot = snapshot;
+
+        if let [plugin] = plugins {
+            self.tracked.add(plugin.name(), changes);
+            return;
+        }
+
+        let unattributed = self.tracked.add_parallel(plugins, changes);
+        if unattributed > 0 {
+            let names = plugins
+                .iter()
+                .map(|plugin| plugin.name())
+                .collect::<Vec<_>>()
+                .join(", ");
+            warn!(
+                "{unattributed} change(s) to tmux could not be attributed to one of the plugins \
+                 loaded in parallel ({names}) and can't be unloaded. Set `parallel = false` for \
+                 them to track all of their changes."
+            );
+        }
+    }
+
+    pub fn finish(self) {
+        if let Err(err) = self.tracked.save() {
+            warn!("Failed to save changes of plugins: {err:#}");
+        }
+    }
+}
+
+/// Directories an init file of a plugin may refer to
+fn plugin_dirs(plugin: &Plugin) -> Vec<PathBuf> {
+    let mut dirs = vec![plugin.path().to_owned()];
+    dirs.extend(plugin.local_path());
+    dirs
+}
+
+/// Run a tmux command, e.g. one returned by `Change::revert_command`. The command is parsed by
+/// tmux, just like a line of the tmux config.
+pub fn run_command(command: &str) -> Result<()> {
+    let mut child = Command::new("tmux")
+        .args(["source-file", "-"])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .spawn()
+        .context("Failed to run `tmux source-file`")?;
+
+    if let Some(mut stdin) = child.stdin.take() {
+        stdin.write_all(format!("{command}\n").as_bytes())?;
+    }
+
+    let output = child.wait_with_output()?;
+    if !output.status.success() {
+        bail!("{}", String::from_utf8_lossy(&output.stderr).trim());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::spec::Spec;
+
+    use super::*;
+
+    fn snapshot(keys: &str, options: &str, hooks: &str) -> Snapshot {
+        let output = format!(
+            "1234\n{SECTION_MARKER}\n{keys}{SECTION_MARKER}\n{options}{SECTION_MARKER}\n\
+             {SECTION_MARKER}\n{SECTION_MARKER}\n{hooks}"
+        );
+        Snapshot::parse(&output).unwrap()
+    }
+
+    #[test]
+    fn test_diff_snapshots() {
+        let before = snapshot(
+            "bind-key    -T prefix       C-b   send-prefix\n\
+             bind-key -r -T prefix       Up    select-pane -U\n",
+            "status-left \"[#S] \"\n",
+            "after-new-window\nclient-attached\n",
+        );
+        let after = snapshot(
+            "bind-key    -T prefix       C-b   send-prefix\n\
+             bind-key    -T prefix       \\\"    display-message hi\n",
+            "status-left \"#S\"\n@plugin-option on\n",
+            "after-new-window[0] run-shell /plugins/a/hook.sh\nclient-attached\n",
+        );
+
+        assert_eq!(after.server_pid, "1234");
+        assert_eq!(
+            after.get(Kind::KeyBinding, "prefix \\\"").unwrap(),
+            "bind-key    -T prefix       \\\"    display-message hi"
+        );
+        assert_eq!(after.get(Kind::Option, "status-left").unwrap(), "\"#S\"");
+
+        let changes = after.diff(&before);
+        let commands = changes
+            .iter()
+            .map(Change::revert_command)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            commands,
+            [
+                "unbind-key -T prefix \\\"",
+                "set-option -gu @plugin-option",
+                "set-option -g status-left \"[#S] \"",
+                "set-hook -gu after-new-window[0]",
+                "bind-key -r -T prefix       Up    select-pane -U",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_revert_hook() {
+        let command = "run-shell \"/plugins/it's a/hook.sh\" ; display-message #{hook}";
+        let before = snapshot("", "", &format!("after-new-window[0] {command}\n"));
+        let after = snapshot("", "", "after-new-window[0] run-shell /plugins/b/hook.sh\n");
+
+        let changes = after.diff(&before);
+        assert_eq!(changes.len(), 1);
+        assert_eq!(changes[0].before.as_deref(), Some(command));
+        assert_eq!(
+            changes[0].revert_command(),
+            r#"set-hook -g after-new-window[0] 'run-shell "/plugins/it'\''s a/hook.sh" ; display-message #{hook}'"#
+        );
+
+        // A hook removed by a plugin is set to its command again
+        let removed = snapshot("", "", "after-new-window\n");
+        let changes = removed.diff(&before);
+        assert_eq!(changes[0].after, None);
+        assert_eq!(
+            changes[0].revert_command(),
+            r#"set-hook -g after-new-window[0] 'run-shell "/plugins/it'\''s a/hook.sh" ; display-message #{hook}'"#
+        );
+    }
+
+    #[test]
+    fn test_add_changes() {
+        let change = |before: Option<&str>, after: Option<&str>| Change {
+            kind: Kind::Option,
+            name: "@a".into(),
+            before: before.map(Into::into),
+            after: after.map(Into::into),
+        };
+
+        let mut tracked = TrackedChanges::default();
+        tracked.add("plugin", vec![change(Some("1"), Some("2"))]);
+        tracked.add("plugin", vec![change(Some("2"), Some("3"))]);
+        assert_eq!(tracked.plugins["plugin"], [change(Some("1"), Some("3"))]);
+
+        tracked.add("plugin", vec![change(Some("3"), Some("1"))]);
+        assert!(tracked.plugins.is_empty());
+    }
+
+    #[test]
+    fn test_add_parallel_changes() {
+        let [a, b] = ["user/a", "user/b"].map(|spec| Plugin::from(Spec::try_from(spec).unwrap()));
+        let change = |name: &str, after: &str| Change {
+            kind: Kind::Hook,
+            name: name.into(),
+            before: None,
+            after: Some(after.into()),
+        };
+        let hook = |plugin: &Plugin| format!("run-shell {}/hook.sh", plugin.path().display());
+
+        let mut tracked = TrackedChanges::default();
+        let unattributed = tracked.add_parallel(
+            &[&a, &b],
+            vec![
+                change("hook-a", &hook(&a)),
+                change("hook-both", &format!("{} ; {}", hook(&a), hook(&b))),
+                change("hook-none", "display-message hi"),
+            ],
+        );
+
+        assert_eq!(unattributed, 2);
+        assert_eq!(tracked.plugins["a"], [change("hook-a", &hook(&a))]);
+        assert!(!tracked.plugins.contains_key("b"));
+        for name in ["a", "b"] {
+            let names = tracked.unattributed[name]
+                .iter()
+                .map(|change| change.name.as_str())
+                .collect::<Vec<_>>();
+            assert_eq!(names, ["hook-both", "hook-none"]);
+        }
+    }
+}
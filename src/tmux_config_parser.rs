@@ -4,15 +4,33 @@ use std::{
 };
 
 use anyhow::{Context, Result};
+use glob::glob;
+use itertools::Itertools;
 use pest::{Parser, iterators::Pair};
 use pest_derive::Parser;
+use strum::IntoEnumIterator;
 
-use crate::spec::Spec;
+use crate::{
+    attribute::Attribute,
+    diagnostic::{Diagnostic, Location},
+    spec::{self, Spec},
+    tmux,
+};
+
+use self::conditional::Conditionals;
 
 #[derive(Debug)]
 pub enum ConfigDirective {
-    PluginSpec(Spec),
-    Source(PathBuf),
+    PluginSpec {
+        spec: Spec,
+        location: Location,
+    },
+    /// A sourced config file. When `quiet` is set (`source-file -q`), a missing file is ignored.
+    Source {
+        path: PathBuf,
+        quiet: bool,
+        location: Location,
+    },
 }
 
 #[derive(Parser)]
@@ -43,6 +61,7 @@ mod source_path {
             match pair.as_rule() {
                 Rule::root_dir => target = PathBuf::from("/"),
                 Rule::config_dir => target = PathBuf::from(config_dir),
+                Rule::home_dir => target = xdir::home().expect("$HOME should be set"),
                 Rule::path => target.push(pair.as_str().trim_start_matches('/')),
 
                 Rule::EOI => (),
@@ -55,67 +74,411 @@ mod source_path {
     }
 }
 
-pub fn parse(config: &Path) -> Result<Vec<ConfigDirective>> {
-    let config_dir = config.parent().expect("Config file should have a parent");
-    let config = fs::read_to_string(config)
-        .context(format!("Failed reading config file: {}", config.display()))?;
+mod conditional {
+    use anyhow::{Result, anyhow};
+
+    use crate::{
+        diagnostic::{Diagnostic, Location},
+        spec::Spec,
+    };
+
+    struct Branch {
+        /// Location of the `%if` that opened the block
+        location: Location,
+        /// Whether the enclosing block is active
+        parent_active: bool,
+        /// Whether the current branch is active
+        active: bool,
+        /// Whether a previous branch's condition was known to be true
+        done: bool,
+        /// Specs defined in the previous branches of the block
+        previous_specs: Vec<Spec>,
+        /// Specs defined in the current branch of the block
+        specs: Vec<Spec>,
+    }
+
+    impl Branch {
+        /// Start the next branch of the block
+        fn next(&mut self) {
+            self.previous_specs.append(&mut self.specs);
+        }
+    }
+
+    /// Tracks nested `%if`/`%elif`/`%else`/`%endif` blocks to decide whether directives are
+    /// active. Conditions are `Option<bool>` where `None` means the condition could not be
+    /// evaluated (e.g. because tmux is not running). Such branches are treated as active, so that
+    /// plugins of all possible branches are considered.
+    #[derive(Default)]
+    pub struct Conditionals {
+        stack: Vec<Branch>,
+    }
+
+    impl Conditionals {
+        pub fn is_active(&self) -> bool {
+            self.stack.last().is_none_or(|branch| branch.active)
+        }
+
+        /// Whether the condition of an `%if` or `%elif` would have any effect, i.e. whether it
+        /// needs to be evaluated
+        pub fn needs_condition(&self, is_elif: bool) -> bool {
+            if is_elif {
+                self.stack
+                    .last()
+                    .is_some_and(|branch| branch.parent_active && !branch.done)
+            } else {
+                self.is_active()
+            }
+        }
+
+        pub fn push_if(&mut self, condition: Option<bool>, location: Location) {
+            let parent_active = self.is_active();
+            self.stack.push(Branch {
+                location,
+                parent_active,
+                active: parent_active && condition != Some(false),
+                done: condition == Some(true),
+                previous_specs: vec![],
+                specs: vec![],
+            });
+        }
+
+        pub fn elif(&mut self, condition: Option<bool>) -> Result<()> {
+            let branch = self
+                .stack
+                .last_mut()
+                .ok_or_else(|| anyhow!("`%elif` without `%if`"))?;
+            branch.active = branch.parent_active && !branch.done && condition != Some(false);
+            branch.done |= condition == Some(true);
+            branch.next();
+            Ok(())
+        }
+
+        pub fn else_(&mut self) -> Result<()> {
+            let branch = self
+                .stack
+                .last_mut()
+                .ok_or_else(|| anyhow!("`%else` without `%if`"))?;
+            branch.active = branch.parent_active && !branch.done;
+            branch.done = true;
+            branch.next();
+            Ok(())
+        }
+
+        pub fn endif(&mut self) -> Result<()> {
+            let mut branch = self
+                .stack
+                .pop()
+                .ok_or_else(|| anyhow!("`%endif` without `%if`"))?;
+            if let Some(parent) = self.stack.last_mut() {
+                branch.next();
+                parent.specs.append(&mut branch.previous_specs);
+            }
+            Ok(())
+        }
+
+        /// Remember a spec of the current branch. Returns `false` if the same spec has been
+        /// defined in a previous branch of an enclosing block already. Several branches are only
+        /// active at the same time when their conditions could not be evaluated, and then it is
+        /// the same plugin in alternative branches rather than a duplicate.
+        pub fn add_spec(&mut self, spec: &Spec) -> bool {
+            if self
+                .stack
+                .iter()
+                .any(|branch| branch.previous_specs.contains(spec))
+            {
+                return false;
+            }
+            if let Some(branch) = self.stack.last_mut() {
+                branch.specs.push(spec.clone());
+            }
+            true
+        }
+
+        pub fn ensure_closed(&self) -> Result<()> {
+            match self.stack.last() {
+                None => Ok(()),
+                Some(branch) => {
+                    Err(Diagnostic::error(branch.location.clone(), "`%if` without `%endif`").into())
+                }
+            }
+        }
+    }
+}
+
+/// Parse a config file and report problems that don't prevent parsing it as warnings
+pub fn parse(path: &Path) -> Result<Vec<ConfigDirective>> {
+    let mut warnings = vec![];
+    let directives = parse_with_warnings(path, &mut warnings)?;
+    warnings.iter().for_each(Diagnostic::report);
+    Ok(directives)
+}
+
+/// Parse a config file. Problems that don't prevent parsing it are added to `warnings`.
+pub fn parse_with_warnings(
+    path: &Path,
+    warnings: &mut Vec<Diagnostic>,
+) -> Result<Vec<ConfigDirective>> {
+    let config_dir = path.parent().expect("Config file should have a parent");
+    let config = fs::read_to_string(path)
+        .context(format!("Failed reading config file: {}", path.display()))?;
 
     let pairs = TmuxConfigParser::parse(Rule::config, &config)
-        .context(format!("Failed to parse config file: {config}"))?;
+        .map_err(|err| err.with_path(&path.to_string_lossy()))
+        .context(format!("Failed to parse config file: {}", path.display()))?;
+
+    let mut directives = vec![];
+    let mut conditionals = Conditionals::default();
+
+    for pair in pairs {
+        let location = Location::from_pair(path, &pair);
+        let at_location = |err: anyhow::Error| Diagnostic::error(location.clone(), err.to_string());
+
+        match pair.as_rule() {
+            Rule::if_directive => {
+                let condition = conditionals
+                    .needs_condition(false)
+                    .then(|| evaluate_condition(pair))
+                    .transpose()?
+                    .flatten();
+                conditionals.push_if(condition, location);
+            }
+            Rule::elif_directive => {
+                let condition = conditionals
+                    .needs_condition(true)
+                    .then(|| evaluate_condition(pair))
+                    .transpose()?
+                    .flatten();
+                conditionals.elif(condition).map_err(at_location)?;
+            }
+            Rule::else_directive => conditionals.else_().map_err(at_location)?,
+            Rule::endif_directive => conditionals.endif().map_err(at_location)?,
+
+            _ if !conditionals.is_active() => (),
 
-    let directives: Vec<ConfigDirective> = pairs
-        .filter_map(|pair| match pair.as_rule() {
-            Rule::plugin_spec => Some(parse_plugin_spec_rule(pair)),
-            Rule::source => Some(parse_source_rule(pair, config_dir)),
+            Rule::plugin_spec => {
+                let directive = parse_plugin_spec_rule(pair, path, warnings)?;
+                if let Some(ConfigDirective::PluginSpec { spec, .. }) = &directive
+                    && !conditionals.add_spec(spec)
+                {
+                    continue;
+                }
+                directives.extend(directive);
+            }
+            Rule::source => directives.extend(parse_source_rule(pair, path, config_dir)?),
 
-            Rule::other | Rule::EOI => None,
+            Rule::other if mentions_plugin_option(pair.as_str()) => {
+                warnings.push(
+                    Diagnostic::warning(location, "Ignoring unrecognized `@plugin` line")
+                        .with_hint(PLUGIN_SPEC_HINT),
+                );
+            }
+            Rule::other | Rule::EOI => (),
 
             Rule::WHITESPACE
             | Rule::COMMENT
             | Rule::config
             | Rule::directive
             | Rule::newline
+            | Rule::line_end
+            | Rule::conditional
+            | Rule::condition
             | Rule::set_option
+            | Rule::set_flags
+            | Rule::set_target
+            | Rule::plugin_option
+            | Rule::plugin_value
             | Rule::source_file
-            | Rule::source_file_flags
+            | Rule::source_flags
+            | Rule::source_target
+            | Rule::source_path
             | Rule::quoted_string
             | Rule::double_quoted_string
             | Rule::single_quoted_string
-            | Rule::quoted_inner => {
+            | Rule::quoted_inner
+            | Rule::unquoted_string => {
                 unreachable!("Unexpected rule: {:?}", pair.as_rule());
             }
-        })
-        .collect::<Result<_>>()?;
+        }
+    }
+
+    conditionals.ensure_closed()?;
 
     Ok(directives)
 }
 
-fn parse_plugin_spec_rule(pair: Pair<'_, Rule>) -> Result<ConfigDirective> {
-    let spec = parse_quoted_string(
-        pair.into_inner()
-            .find(|x| x.as_rule() == Rule::quoted_string)
-            .context("`quoted_string` should exist inside `plugin_spec`")?,
-    )
-    .context("Failed to parse `quoted_string` of `plugin_spec`")?;
-    Ok(ConfigDirective::PluginSpec(
-        spec.as_str()
-            .try_into()
-            .with_context(|| format!("Failed to parse plugin spec: {spec}"))?,
-    ))
+/// Evaluate the condition of an `%if` or `%elif` by letting tmux expand it. Like in tmux, the
+/// condition is true if it expands to a non-empty string other than `0`. Conditions without
+/// formats don't need to be expanded. Returns `None` if tmux is not running and the condition can
+/// therefore not be evaluated.
+fn evaluate_condition(pair: Pair<'_, Rule>) -> Result<Option<bool>> {
+    let condition = pair
+        .into_inner()
+        .next()
+        .context("Conditional should have a condition")?;
+    let condition = match condition.as_rule() {
+        Rule::quoted_string => parse_quoted_string(condition)?,
+        _ => condition.as_str().to_owned(),
+    };
+
+    let is_true = |value: &str| !value.is_empty() && value != "0";
+    if !condition.contains('#') {
+        return Ok(Some(is_true(&condition)));
+    }
+    Ok(tmux::expand_format(&condition).map(|value| is_true(&value)))
 }
 
-fn parse_source_rule(pair: Pair<'_, Rule>, config_dir: &Path) -> Result<ConfigDirective> {
-    let path = parse_quoted_string(
-        pair.into_inner()
-            .find(|x| x.as_rule() == Rule::quoted_string)
-            .context("`quoted_string` should exist inside `source`")?,
-    )
-    .context("Failed to parse `quoted_string` of `source`")?;
+const PLUGIN_SPEC_HINT: &str =
+    "Plugins are specified like `set -g @plugin 'user/repo#branch; attribute=value'`";
+
+fn parse_plugin_spec_rule(
+    pair: Pair<'_, Rule>,
+    path: &Path,
+    warnings: &mut Vec<Diagnostic>,
+) -> Result<Option<ConfigDirective>> {
+    let location = Location::from_pair(path, &pair);
+    let mut flags = String::new();
+    let mut value = None;
+
+    for pair in pair.into_inner() {
+        match pair.as_rule() {
+            Rule::set_flags => flags.push_str(pair.as_str().trim_start_matches('-')),
+            Rule::quoted_string => {
+                // The spec starts after the opening quote
+                let spec_location = Location::from_pair(path, &pair).offset(1);
+                let spec = parse_quoted_string(pair)
+                    .context("Failed to parse `quoted_string` of `plugin_spec`")?;
+                value = Some((spec, spec_location));
+            }
+            Rule::unquoted_string => {
+                value = Some((pair.as_str().to_owned(), Location::from_pair(path, &pair)))
+            }
+            _ => (),
+        }
+    }
+
+    // `set -u` unsets the option and therefore does not define a plugin
+    if flags.contains('u') {
+        return Ok(None);
+    }
+
+    let Some((value, spec_location)) = value else {
+        warnings.push(
+            Diagnostic::warning(location, "Ignoring `@plugin` line without a plugin spec")
+                .with_hint(PLUGIN_SPEC_HINT),
+        );
+        return Ok(None);
+    };
 
-    let path = source_path::parse(&path, config_dir)
-        .with_context(|| format!("Failed parsing source_path: {path}"))?;
+    let (spec, unknown_attributes) = Spec::parse(&value).map_err(|err| {
+        let (message, offset) = spec::describe_parse_error(&err);
+        Diagnostic::error(
+            spec_location.offset(offset.unwrap_or(0)),
+            format!("Invalid plugin spec: {message}"),
+        )
+        .with_hint(PLUGIN_SPEC_HINT)
+    })?;
 
-    Ok(ConfigDirective::Source(path.to_owned()))
+    for attribute in unknown_attributes {
+        warnings.push(
+            Diagnostic::warning(
+                spec_location.offset(attribute.offset),
+                format!("Ignoring unknown attribute `{}`", attribute.key),
+            )
+            .with_hint(format!(
+                "Known attributes are: {}",
+                Attribute::iter().join(", ")
+            )),
+        );
+    }
+
+    Ok(Some(ConfigDirective::PluginSpec { spec, location }))
+}
+
+/// Whether a line mentions the `@plugin` option (and not just an option starting with
+/// `@plugin`, like `@plugin-foo`)
+fn mentions_plugin_option(line: &str) -> bool {
+    line.match_indices("@plugin").any(|(idx, needle)| {
+        line[idx + needle.len()..]
+            .chars()
+            .next()
+            .is_none_or(|c| !(c.is_alphanumeric() || c == '-' || c == '_'))
+    })
+}
+
+pub const SOURCE_QUIET_HINT: &str = "Use `source-file -q` to ignore missing files";
+
+fn parse_source_rule(
+    pair: Pair<'_, Rule>,
+    config: &Path,
+    config_dir: &Path,
+) -> Result<Vec<ConfigDirective>> {
+    let mut flags = String::new();
+    let mut paths = vec![];
+
+    for pair in pair.into_inner() {
+        let location = Location::from_pair(config, &pair);
+        match pair.as_rule() {
+            Rule::source_flags => flags.push_str(pair.as_str().trim_start_matches('-')),
+            Rule::quoted_string => paths.push((
+                parse_quoted_string(pair).context("Failed to parse `quoted_string` of `source`")?,
+                location,
+            )),
+            Rule::unquoted_string => paths.push((pair.as_str().to_owned(), location)),
+
+            Rule::source_file | Rule::source_target => (),
+
+            _ => unreachable!("Unexpected rule in `source`: {:?}", pair.as_rule()),
+        }
+    }
+
+    // With `-n`, tmux only checks the syntax of the file but does not run any commands in it
+    if flags.contains('n') {
+        return Ok(vec![]);
+    }
+    let quiet = flags.contains('q');
+
+    let mut directives = vec![];
+    for (path, location) in paths {
+        let path = source_path::parse(&path, config_dir)
+            .with_context(|| format!("Failed parsing source_path: {path}"))?;
+
+        let paths = expand_glob(&path)?;
+        if paths.is_empty() && !quiet {
+            return Err(Diagnostic::error(
+                location,
+                format!("No files match source pattern: {}", path.display()),
+            )
+            .with_hint(SOURCE_QUIET_HINT)
+            .into());
+        }
+
+        for path in paths {
+            directives.push(ConfigDirective::Source {
+                path,
+                quiet,
+                location: location.clone(),
+            });
+        }
+    }
+
+    Ok(directives)
+}
+
+/// Expand a source path containing glob patterns into the sorted list of matching paths, just
+/// like tmux does. Paths without glob patterns are returned as they are.
+fn expand_glob(path: &Path) -> Result<Vec<PathBuf>> {
+    let pattern = path.to_str().context("Path is not valid UTF-8")?;
+
+    if !pattern.contains(['*', '?', '[']) {
+        return Ok(vec![path.to_owned()]);
+    }
+
+    let mut paths = glob(pattern)
+        .with_context(|| format!("Invalid glob pattern: {pattern}"))?
+        .collect::<Result<Vec<_>, _>>()?;
+    paths.sort();
+
+    Ok(paths)
 }
 
 fn parse_quoted_string(pair: Pair<'_, Rule>) -> Result<String> {
@@ -146,3 +509,237 @@ fn parse_quoted_string(pair: Pair<'_, Rule>) -> Result<String> {
         ),
     })
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::setup_dir;
+
+    fn sourced_paths(directives: &[ConfigDirective]) -> Vec<(PathBuf, bool)> {
+        directives
+            .iter()
+            .filter_map(|directive| match directive {
+                ConfigDirective::Source { path, quiet, .. } => Some((path.to_owned(), *quiet)),
+                ConfigDirective::PluginSpec { .. } => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_source_glob() {
+        let dir = setup_dir(
+            "source-glob",
+            &[(
+                "tmux.conf",
+                "source-file '#{d:current_file}/conf.d/*.conf'\n",
+            )],
+        );
+        fs::create_dir(dir.join("conf.d")).unwrap();
+        fs::write(dir.join("conf.d/b.conf"), "").unwrap();
+        fs::write(dir.join("conf.d/a.conf"), "").unwrap();
+        fs::write(dir.join("conf.d/c.txt"), "").unwrap();
+
+        let directives = parse(&dir.join("tmux.conf")).unwrap();
+        assert_eq!(
+            sourced_paths(&directives),
+            vec![
+                (dir.join("conf.d/a.conf"), false),
+                (dir.join("conf.d/b.conf"), false)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_source_glob_without_matches() {
+        let dir = setup_dir(
+            "source-glob-without-matches",
+            &[("tmux.conf", "source '#{d:current_file}/*.conf.d'\n")],
+        );
+        assert!(parse(&dir.join("tmux.conf")).is_err());
+
+        let dir = setup_dir(
+            "source-glob-without-matches-quiet",
+            &[("tmux.conf", "source -q '#{d:current_file}/*.conf.d'\n")],
+        );
+        assert_eq!(
+            sourced_paths(&parse(&dir.join("tmux.conf")).unwrap()),
+            vec![]
+        );
+    }
+
+    #[test]
+    fn test_source_flags_and_unquoted_paths() {
+        let dir = setup_dir(
+            "source-flags",
+            &[(
+                "tmux.conf",
+                "source-file -q /tmp/a.conf /tmp/b.conf # comment\n\
+                 source -n /tmp/c.conf\n\
+                 source ~/d.conf\n",
+            )],
+        );
+        let home = xdir::home().unwrap();
+
+        assert_eq!(
+            sourced_paths(&parse(&dir.join("tmux.conf")).unwrap()),
+            vec![
+                (PathBuf::from("/tmp/a.conf"), true),
+                (PathBuf::from("/tmp/b.conf"), true),
+                (home.join("d.conf"), false),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_conditionals() {
+        let location = Location::new(Path::new("tmux.conf"), 1, 1);
+        let mut conditionals = Conditionals::default();
+        assert!(conditionals.is_active());
+
+        conditionals.push_if(Some(false), location.clone());
+        assert!(!conditionals.is_active());
+        assert!(conditionals.needs_condition(true));
+        conditionals.elif(Some(true)).unwrap();
+        assert!(conditionals.is_active());
+
+        // Nested blocks inside an active branch
+        conditionals.push_if(Some(true), location.clone());
+        assert!(conditionals.is_active());
+        conditionals.else_().unwrap();
+        assert!(!conditionals.is_active());
+        conditionals.endif().unwrap();
+
+        // No further branch is taken once a condition was true
+        assert!(!conditionals.needs_condition(true));
+        conditionals.elif(Some(true)).unwrap();
+        assert!(!conditionals.is_active());
+        conditionals.else_().unwrap();
+        assert!(!conditionals.is_active());
+        conditionals.endif().unwrap();
+
+        // Unknown conditions keep all branches active
+        conditionals.push_if(None, location.clone());
+        assert!(conditionals.is_active());
+        conditionals.else_().unwrap();
+        assert!(conditionals.is_active());
+        conditionals.endif().unwrap();
+
+        assert!(conditionals.ensure_closed().is_ok());
+        assert!(conditionals.endif().is_err());
+        assert!(conditionals.else_().is_err());
+    }
+
+    #[test]
+    fn test_conditional_specs() {
+        let spec = |value| Spec::try_from(value).unwrap();
+        let location = Location::new(Path::new("tmux.conf"), 1, 1);
+        let mut conditionals = Conditionals::default();
+
+        // Duplicates outside of conditionals or within one branch are kept
+        assert!(conditionals.add_spec(&spec("user/a")));
+        assert!(conditionals.add_spec(&spec("user/a")));
+
+        conditionals.push_if(None, location.clone());
+        assert!(conditionals.add_spec(&spec("user/a")));
+        assert!(conditionals.add_spec(&spec("user/b")));
+        assert!(conditionals.add_spec(&spec("user/b")));
+        conditionals.elif(None).unwrap();
+        assert!(!conditionals.add_spec(&spec("user/b")));
+        assert!(conditionals.add_spec(&spec("user/b#main")));
+        conditionals.else_().unwrap();
+
+        // Specs of nested blocks belong to the branch they are in
+        conditionals.push_if(None, location.clone());
+        assert!(!conditionals.add_spec(&spec("user/a")));
+        assert!(conditionals.add_spec(&spec("user/c")));
+        conditionals.endif().unwrap();
+        conditionals.endif().unwrap();
+
+        // Specs of other blocks are duplicates
+        conditionals.push_if(None, location.clone());
+        conditionals.else_().unwrap();
+        assert!(conditionals.add_spec(&spec("user/c")));
+        conditionals.endif().unwrap();
+    }
+
+    #[test]
+    fn test_conditional_plugin_specs() {
+        let dir = setup_dir(
+            "conditional-plugin-specs",
+            &[(
+                "tmux.conf",
+                "set -g @plugin 'user/a'
+                 %if 0
+                 set -g @plugin 'user/b'
+                 %elif 1
+                 set -g @plugin 'user/c'
+                 %if ''
+                 set -g @plugin 'user/d'
+                 %else
+                 set -g @plugin 'user/e'
+                 %endif
+                 %elif 1
+                 set -g @plugin 'user/f'
+                 %else
+                 set -g @plugin 'user/g'
+                 %endif
+                 %if \"1\"
+                 set -g @plugin 'user/h'
+                 %endif
+",
+            )],
+        );
+
+        assert_eq!(
+            plugin_names(&parse(&dir.join("tmux.conf")).unwrap()),
+            vec!["a", "c", "e", "h"]
+        );
+    }
+
+    #[test]
+    fn test_unbalanced_conditionals() {
+        let dir = setup_dir("unbalanced-conditionals", &[("tmux.conf", "%endif\n")]);
+        assert!(parse(&dir.join("tmux.conf")).is_err());
+    }
+
+    fn plugin_names(directives: &[ConfigDirective]) -> Vec<String> {
+        directives
+            .iter()
+            .filter_map(|directive| match directive {
+                ConfigDirective::PluginSpec { spec, .. } => Some(spec.name().to_owned()),
+                ConfigDirective::Source { .. } => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_plugin_spec_variants() {
+        let dir = setup_dir(
+            "plugin-spec-variants",
+            &[(
+                "tmux.conf",
+                "set -g @plugin 'user/a'\n\
+                 set-option -g @plugin \"user/b\"\n\
+                 set -ga @plugin 'user/c'\n\
+                 set-option -gq @plugin user/d # comment\n\
+                 setw -g @plugin user/e\n\
+                 set -g \\\n  @plugin \\\n  'user/f'\n\
+                 set -gu @plugin\n\
+                 set -g @plugin-foo 'user/nope'\n",
+            )],
+        );
+
+        assert_eq!(
+            plugin_names(&parse(&dir.join("tmux.conf")).unwrap()),
+            vec!["a", "b", "c", "d", "e", "f"]
+        );
+    }
+
+    #[test]
+    fn test_mentions_plugin_option() {
+        assert!(mentions_plugin_option("set -g @plugin"));
+        assert!(mentions_plugin_option("set -g @plugin 'a' 'b'"));
+        assert!(!mentions_plugin_option("set -g @plugin-dir foo"));
+        assert!(!mentions_plugin_option("set -g @plugins foo"));
+    }
+}
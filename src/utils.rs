@@ -1,4 +1,4 @@
-use std::{collections::HashSet, hash::Hash};
+use std::{collections::HashSet, hash::Hash, path::PathBuf, time::Duration};
 
 pub fn dedup_by_key<T, K>(items: Vec<T>, key: impl Fn(&T) -> K) -> Vec<T>
 where
@@ -45,3 +45,82 @@ pub fn parse_bool(value: &str) -> Option<bool> {
         _ => None,
     }
 }
+
+/// A duration in milliseconds, as used in JSON output and logs
+pub fn as_millis(duration: Duration) -> f64 {
+    duration.as_secs_f64() * 1000.0
+}
+
+/// Parse a duration given in seconds (`10`, `1.5`, `10s`) or milliseconds (`500ms`)
+pub fn parse_duration(value: &str) -> Option<Duration> {
+    let value = value.trim();
+    let (number, scale) = match value.strip_suffix("ms") {
+        Some(millis) => (millis, 1000.0),
+        None => (value.strip_suffix('s').unwrap_or(value), 1.0),
+    };
+
+    number
+        .trim()
+        .parse::<f64>()
+        .ok()
+        .and_then(|number| Duration::try_from_secs_f64(number / scale).ok())
+}
+
+/// Quote a string for use in a shell or tmux command
+pub fn shell_quote(value: &str) -> String {
+    format!("'{}'", value.replace('\'', r"'\''"))
+}
+
+/// Expand a leading `~`, `$HOME` or `${HOME}` in a path to the home directory
+pub fn expand_home(path: &str) -> PathBuf {
+    let home = || xdir::home().expect("$HOME should be set");
+
+    for prefix in ["~", "$HOME", "${HOME}"] {
+        if path == prefix {
+            return home();
+        }
+        if let Some(rest) = path.strip_prefix(prefix).and_then(|p| p.strip_prefix('/')) {
+            return home().join(rest);
+        }
+    }
+
+    PathBuf::from(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_expand_home() {
+        let home = xdir::home().unwrap();
+        assert_eq!(expand_home("~"), home);
+        assert_eq!(expand_home("~/.tmux/plugins"), home.join(".tmux/plugins"));
+        assert_eq!(expand_home("$HOME/.tmux"), home.join(".tmux"));
+        assert_eq!(expand_home("${HOME}/.tmux"), home.join(".tmux"));
+    }
+
+    #[test]
+    fn test_parse_duration() {
+        assert_eq!(parse_duration("10"), Some(Duration::from_secs(10)));
+        assert_eq!(parse_duration("1.5"), Some(Duration::from_millis(1500)));
+        assert_eq!(parse_duration("2s"), Some(Duration::from_secs(2)));
+        assert_eq!(parse_duration("500ms"), Some(Duration::from_millis(500)));
+        assert_eq!(parse_duration("0"), Some(Duration::ZERO));
+        assert_eq!(parse_duration("-1"), None);
+        assert_eq!(parse_duration("soon"), None);
+    }
+
+    #[test]
+    fn test_shell_quote() {
+        assert_eq!(shell_quote("plain"), "'plain'");
+        assert_eq!(shell_quote("it's $HOME"), r"'it'\''s $HOME'");
+    }
+
+    #[test]
+    fn test_expand_home_keeps_other_paths() {
+        assert_eq!(expand_home("/tmp/~"), PathBuf::from("/tmp/~"));
+        assert_eq!(expand_home("~user/dir"), PathBuf::from("~user/dir"));
+        assert_eq!(expand_home("$HOMEDIR/x"), PathBuf::from("$HOMEDIR/x"));
+    }
+}